@@ -0,0 +1,54 @@
+//! Throughput of the single buffer format against the chunked format on one and on all cores.
+//!
+//! Run with `cargo bench`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use enchanter::{open, open_chunked, seal, seal_chunked, DEFAULT_CHUNK_SIZE};
+
+use std::hint::black_box;
+use std::thread;
+
+const KEY: [u8; 32] = [7u8; 32];
+const SIZE: usize = 64 << 20;
+
+fn threads() -> Vec<usize> {
+    let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut threads = vec![1];
+    if cores > 1 {
+        threads.push(cores);
+    }
+    threads
+}
+
+fn encrypt(c: &mut Criterion) {
+    let plaintext = vec![0x5au8; SIZE];
+    let mut group = c.benchmark_group("encrypt");
+    group.throughput(Throughput::Bytes(SIZE as u64));
+    group.sample_size(10);
+    group.bench_function("single buffer", |b| b.iter(|| seal(black_box(&plaintext), &KEY).unwrap()));
+    for threads in threads() {
+        group.bench_with_input(BenchmarkId::new("chunked", threads), &threads, |b, &threads| {
+            b.iter(|| seal_chunked(black_box(&plaintext), &KEY, DEFAULT_CHUNK_SIZE, threads).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn decrypt(c: &mut Criterion) {
+    let plaintext = vec![0x5au8; SIZE];
+    let single = seal(&plaintext, &KEY).unwrap();
+    let chunked = seal_chunked(&plaintext, &KEY, DEFAULT_CHUNK_SIZE, 1).unwrap();
+    let mut group = c.benchmark_group("decrypt");
+    group.throughput(Throughput::Bytes(SIZE as u64));
+    group.sample_size(10);
+    group.bench_function("single buffer", |b| b.iter(|| open(black_box(&single), &KEY).unwrap()));
+    for threads in threads() {
+        group.bench_with_input(BenchmarkId::new("chunked", threads), &threads, |b, &threads| {
+            b.iter(|| open_chunked(black_box(&chunked), &KEY, threads).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encrypt, decrypt);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+//! Generates the C header for the ffi feature into OUT_DIR. The copy in include/enchanter.h is
+//! checked against it by the ffi tests.
+
+fn main() {
+    #[cfg(feature = "ffi")]
+    header();
+}
+
+#[cfg(feature = "ffi")]
+fn header() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set by cargo");
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    cbindgen::generate(&crate_dir)
+      .expect("Failed to generate the C header")
+      .write_to_file(std::path::Path::new(&out_dir).join("enchanter.h"));
+}
@@ -0,0 +1,521 @@
+//! The enchanter agent is a small background process that caches keys derived with a3,
+//! so that many files can be processed with a single password prompt and a single run
+//! of the (expensive) triple Argon2id derivation.
+//!
+//! The agent listens on a Unix domain socket that only the owning user can reach.
+//! Keys are held in locked (mlock) memory, zeroized when forgotten, and expire after
+//! an idle timeout. The protocol is one request line and one response per connection:
+//!
+//! ```text
+//! ADD <name> <base64 key> [idle timeout seconds] -> OK
+//! GET <name>                                       -> KEY <base64 key> | ERR <message>
+//! FIND <validation string> <ciphertext>            -> KEY <base64 key> | ERR <message>
+//! LIST                                             -> ENTRY <name> <fingerprint> <seconds left>... END
+//! FORGET <name>                                    -> OK | ERR <message>
+//! FORGETALL                                        -> OK
+//! STOP                                             -> OK
+//! ```
+//!
+//! FIND sends the ciphertext as raw bytes after its line, up to the end of the request, and the
+//! agent answers with the key whose validation string for them is the given one, so other keys
+//! never leave the agent.
+
+use base64::prelude::*;
+use zeroize::Zeroize;
+
+use std::collections::HashMap;
+use std::env;
+use std::error::Error as StdError;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use enchanter::TUR;
+use enchanter::Error;
+use crate::output::{AgentKey, Report};
+
+/// The default idle timeout for cached keys, in seconds.
+const DEFAULT_TIMEOUT: u64 = 600;
+
+/// The name used for a cached key when none is supplied.
+pub const DEFAULT_NAME: &str = "default";
+
+/// A 32 byte key held in memory that is locked into RAM with mlock,
+/// so that it is not written to swap, and zeroized on drop.
+struct LockedKey {
+    bytes: Box<[u8; 32]>,
+}
+
+impl LockedKey {
+    fn new(key: &[u8; 32]) -> LockedKey {
+        let mut bytes = Box::new([0u8; 32]);
+        // Lock the page before the key material is copied into it.
+        unsafe {
+            libc::mlock(bytes.as_ptr() as *const libc::c_void, 32);
+        }
+        bytes.copy_from_slice(key);
+        LockedKey { bytes }
+    }
+}
+
+impl Drop for LockedKey {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+        unsafe {
+            libc::munlock(self.bytes.as_ptr() as *const libc::c_void, 32);
+        }
+    }
+}
+
+/// A cached key and its idle timeout bookkeeping.
+struct Entry {
+    key: LockedKey,
+    timeout: Duration,
+    last_used: Instant,
+}
+
+impl Entry {
+    fn remaining(&self) -> Duration {
+        self.timeout.saturating_sub(self.last_used.elapsed())
+    }
+}
+
+type Store = Arc<Mutex<HashMap<String, Entry>>>;
+
+/// The socket path is taken from ENCHANTER_AGENT_SOCK if set, otherwise it is placed in
+/// XDG_RUNTIME_DIR, falling back to a per user directory in /tmp.
+pub fn socket_path() -> PathBuf {
+    if let Ok(path) = env::var("ENCHANTER_AGENT_SOCK") {
+        return PathBuf::from(path);
+    }
+    if let Ok(dir) = env::var("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir).join("enchanter-agent.sock");
+    }
+    let uid = unsafe { libc::getuid() };
+    PathBuf::from(format!("/tmp/enchanter-{uid}")).join("agent.sock")
+}
+
+/// Look up the uid of the process on the other end of the socket.
+#[cfg(target_os = "linux")]
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    use std::os::unix::io::AsRawFd;
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if rc == 0 { Some(cred.uid) } else { None }
+}
+
+/// Other unix platforms rely on the 0700 socket directory and 0600 socket.
+#[cfg(not(target_os = "linux"))]
+fn peer_uid(_stream: &UnixStream) -> Option<u32> {
+    Some(unsafe { libc::getuid() })
+}
+
+/// Short non-secret identifier for a key, so cached keys can be told apart in a listing.
+fn fingerprint(key: &[u8]) -> String {
+    BASE64_STANDARD.encode(enchanter::ciphertext_hash(key, b"enchanter-agent-fingerprint", 9))
+}
+
+/// Send one request line to the agent and collect the response lines.
+fn request(line: &str) -> io::Result<Vec<String>> {
+    request_at(&socket_path(), line, &[])
+}
+
+/// Send one request line, followed by any data, to the agent on the given socket and collect
+/// the response lines.
+fn request_at(path: &Path, line: &str, data: &[u8]) -> io::Result<Vec<String>> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.write_all(line.as_bytes())?;
+    stream.write_all(b"\n")?;
+    stream.write_all(data)?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+    let reader = BufReader::new(stream);
+    reader.lines().collect()
+}
+
+/// Turn a single line agent response into a result.
+fn expect_ok(response: Vec<String>) -> Result<(), Box<dyn StdError>> {
+    match response.first().map(String::as_str) {
+        Some("OK") => Ok(()),
+        Some(other) => Err(io::Error::other(format!("Agent refused the request: {}", other.trim_start_matches("ERR "))).into()),
+        None => Err(io::Error::other("Agent closed the connection without a response").into()),
+    }
+}
+
+/// Fetch the cached key whose validation string for the ciphertext is the expected one. Any
+/// failure to reach the agent is treated as "no key", so the CLI can fall back to prompting.
+pub fn find_key(expected: &str, data: &[u8]) -> Option<[u8; 32]> {
+    parse_key(request_at(&socket_path(), &format!("FIND {expected}"), data).ok()?)
+}
+
+/// The key in a KEY response.
+fn parse_key(response: Vec<String>) -> Option<[u8; 32]> {
+    let line = response.first()?;
+    let encoded = line.strip_prefix("KEY ")?;
+    let mut decoded = BASE64_STANDARD.decode(encoded).ok()?;
+    let mut key = [0u8; 32];
+    if decoded.len() == 32 {
+        key.copy_from_slice(&decoded);
+        decoded.zeroize();
+        Some(key)
+    } else {
+        decoded.zeroize();
+        None
+    }
+}
+
+/// Write a KEY response for a cached key, marking it as used.
+fn send_key(out: &mut UnixStream, entry: &mut Entry) -> io::Result<()> {
+    entry.last_used = Instant::now();
+    let mut response = format!("KEY {}\n", BASE64_STANDARD.encode(&entry.key.bytes[..]));
+    let written = out.write_all(response.as_bytes());
+    response.zeroize();
+    written
+}
+
+/// Serve a single connection.
+fn handle(stream: UnixStream, store: &Store, running: &Arc<Mutex<bool>>, default_timeout: u64) -> io::Result<()> {
+    let me = unsafe { libc::getuid() };
+    if peer_uid(&stream) != Some(me) {
+        return Ok(());
+    }
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.trim_end().split(' ');
+    let command = parts.next().unwrap_or("");
+    let mut data = Vec::new();
+    if command == "FIND" {
+        reader.read_to_end(&mut data)?;
+    }
+    let mut out = stream;
+    let mut entries = store.lock().map_err(|_| io::Error::other("agent state poisoned"))?;
+    match command {
+        "ADD" => {
+            let name = parts.next().unwrap_or(DEFAULT_NAME).to_string();
+            let mut decoded = BASE64_STANDARD.decode(parts.next().unwrap_or("")).unwrap_or_default();
+            let timeout = parts.next().and_then(|t| t.parse::<u64>().ok()).unwrap_or(default_timeout);
+            if decoded.len() == 32 {
+                let mut key = [0u8; 32];
+                key.copy_from_slice(&decoded);
+                entries.insert(name, Entry { key: LockedKey::new(&key), timeout: Duration::from_secs(timeout), last_used: Instant::now() });
+                key.zeroize();
+                out.write_all(b"OK\n")?;
+            } else {
+                out.write_all(b"ERR key must be 32 bytes\n")?;
+            }
+            decoded.zeroize();
+        },
+        "GET" => {
+            let name = parts.next().unwrap_or(DEFAULT_NAME);
+            match entries.get_mut(name) {
+                Some(entry) => send_key(&mut out, entry)?,
+                None => out.write_all(b"ERR no such key\n")?,
+            }
+        },
+        "FIND" => {
+            let expected = parts.next().unwrap_or("");
+            match entries.values_mut().find(|entry| enchanter::validation_string(&entry.key.bytes[..], &data) == expected) {
+                Some(entry) => send_key(&mut out, entry)?,
+                None => out.write_all(b"ERR no matching key\n")?,
+            }
+        },
+        "LIST" => {
+            for (name, entry) in entries.iter() {
+                let line = format!("ENTRY {} {} {}\n", name, fingerprint(&entry.key.bytes[..]), entry.remaining().as_secs());
+                out.write_all(line.as_bytes())?;
+            }
+            out.write_all(b"END\n")?;
+        },
+        "FORGET" => {
+            let name = parts.next().unwrap_or(DEFAULT_NAME);
+            if entries.remove(name).is_some() {
+                out.write_all(b"OK\n")?;
+            } else {
+                out.write_all(b"ERR no such key\n")?;
+            }
+        },
+        "FORGETALL" => {
+            entries.clear();
+            out.write_all(b"OK\n")?;
+        },
+        "STOP" => {
+            entries.clear();
+            if let Ok(mut flag) = running.lock() {
+                *flag = false;
+            }
+            out.write_all(b"OK\n")?;
+        },
+        _ => out.write_all(b"ERR unknown command\n")?,
+    }
+    drop(entries);
+    line.zeroize();
+    Ok(())
+}
+
+/// Bind the agent socket with owner-only permissions, replacing a stale socket file.
+fn bind(path: &PathBuf) -> Result<UnixListener, Box<dyn StdError>> {
+    if let Some(parent) = path.parent() && !parent.exists() {
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(parent)?;
+    }
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::other(format!("An agent is already listening on {}", path.display())).into());
+        }
+        fs::remove_file(path)?;
+    }
+    let old = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(old) };
+    let listener = listener?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Run the agent in the foreground until it receives STOP.
+fn serve(default_timeout: u64) -> Result<(), Box<dyn StdError>> {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        // Keep key material out of core dumps and away from ptrace by other processes.
+        libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0);
+    }
+    serve_at(&socket_path(), default_timeout, Duration::from_secs(1))
+}
+
+/// Serve on the given socket until STOP, dropping idle keys every reap interval.
+fn serve_at(path: &PathBuf, default_timeout: u64, reap_interval: Duration) -> Result<(), Box<dyn StdError>> {
+    let listener = bind(path)?;
+    let store: Store = Arc::new(Mutex::new(HashMap::new()));
+    let running = Arc::new(Mutex::new(true));
+
+    let (reaper_store, reaper_running) = (Arc::clone(&store), Arc::clone(&running));
+    thread::spawn(move || loop {
+        thread::sleep(reap_interval);
+        if !reaper_running.lock().map(|flag| *flag).unwrap_or(false) {
+            break;
+        }
+        if let Ok(mut entries) = reaper_store.lock() {
+            entries.retain(|_, entry| !entry.remaining().is_zero());
+        }
+    });
+
+    for stream in listener.incoming() {
+        if let Ok(stream) = stream {
+            let _ = handle(stream, &store, &running, default_timeout);
+        }
+        if !*running.lock().map_err(|_| io::Error::other("agent state poisoned"))? {
+            break;
+        }
+    }
+    let _ = fs::remove_file(path);
+    Ok(())
+}
+
+/// Start the agent as a detached background process and wait for its socket to answer.
+fn start(default_timeout: u64) -> Result<(), Box<dyn StdError>> {
+    let path = socket_path();
+    let exe = env::current_exe()?;
+    let mut child = Command::new(exe);
+    child.args(["agent", "--foreground", "--timeout", &default_timeout.to_string()]);
+    child.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    unsafe {
+        child.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    child.spawn()?;
+    for _ in 0..50 {
+        if UnixStream::connect(&path).is_ok() {
+            let mut report = Report::ok("agent").message("agent started");
+            report.socket = Some(path.display().to_string());
+            report.emit();
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Err(io::Error::other(format!("The agent did not start listening on {}", path.display())).into())
+}
+
+/// Prompt for a password, derive the key, and hand it to the agent.
+fn add(name: &str, timeout: Option<u64>) -> Result<(), Box<dyn StdError>> {
+    if name.is_empty() || name.chars().any(char::is_whitespace) {
+        return Err(Error::Usage("Key names must not be empty or contain whitespace".into()).into());
+    }
+    let mut password = crate::prompt_password(&format!("Enter the password to cache in the agent as {name}"), false, None)?;
+    let mut key = enchanter::a3(&password, TUR);
+    password.zeroize();
+    let mut encoded = BASE64_STANDARD.encode(key);
+    key.zeroize();
+    let mut line = match timeout {
+        Some(timeout) => format!("ADD {name} {encoded} {timeout}"),
+        None => format!("ADD {name} {encoded}"),
+    };
+    let response = request(&line);
+    encoded.zeroize();
+    line.zeroize();
+    expect_ok(response?)?;
+    let mut report = Report::ok("agent").message("key added");
+    report.name = Some(name.to_string());
+    report.emit();
+    Ok(())
+}
+
+/// Print the cached key names, fingerprints and remaining idle time.
+fn list() -> Result<(), Box<dyn StdError>> {
+    let lines = request("LIST")?;
+    let keys: Vec<AgentKey> = lines
+        .iter()
+        .filter_map(|line| line.strip_prefix("ENTRY "))
+        .map(|entry| {
+            let fields: Vec<&str> = entry.split(' ').collect();
+            AgentKey {
+                name: fields.first().unwrap_or(&"").to_string(),
+                fingerprint: fields.get(1).unwrap_or(&"").to_string(),
+                seconds_remaining: fields.get(2).and_then(|s| s.parse().ok()).unwrap_or(0),
+            }
+        })
+        .collect();
+    let mut report = Report::ok("agent");
+    report.keys = Some(keys);
+    report.emit();
+    Ok(())
+}
+
+/// Handle `enchanter agent [--foreground] [add|list|forget|stop] ...`.
+pub fn command(args: &[String]) -> Result<(), Box<dyn StdError>> {
+    let mut timeout = None;
+    let mut foreground = false;
+    let mut positional: Vec<&str> = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--foreground" => foreground = true,
+            "--timeout" => {
+                let value = iter.next().ok_or_else(|| Error::Usage("--timeout requires a number of seconds".into()))?;
+                timeout = Some(value.parse().map_err(|_| Error::Usage(format!("Invalid timeout: {value}")))?);
+            },
+            other => positional.push(other),
+        }
+    }
+
+    match positional.as_slice() {
+        [] if foreground => serve(timeout.unwrap_or(DEFAULT_TIMEOUT)),
+        [] => start(timeout.unwrap_or(DEFAULT_TIMEOUT)),
+        ["add"] => add(DEFAULT_NAME, timeout),
+        ["add", name] => add(name, timeout),
+        ["list"] => list(),
+        ["forget"] => {
+            expect_ok(request("FORGETALL")?)?;
+            Report::ok("agent").message("all keys forgotten").emit();
+            Ok(())
+        },
+        ["forget", name] => {
+            expect_ok(request(&format!("FORGET {name}"))?)?;
+            let mut report = Report::ok("agent").message("key forgotten");
+            report.name = Some(name.to_string());
+            report.emit();
+            Ok(())
+        },
+        ["stop"] => {
+            expect_ok(request("STOP")?)?;
+            Report::ok("agent").message("agent stopped").emit();
+            Ok(())
+        },
+        _ => Err(Error::Usage("Usage: enchanter agent [--foreground] [--timeout SECONDS] [add [name] | list | forget [name] | stop]".into()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start an agent on a socket of its own and wait for it to answer.
+    fn start_agent(name: &str, reap_interval: Duration) -> (PathBuf, thread::JoinHandle<()>) {
+        let dir = env::temp_dir().join(format!("enchanter-agent-{}-{name}", std::process::id()));
+        let path = dir.join("agent.sock");
+        let _ = fs::remove_dir_all(&dir);
+        let serve_path = path.clone();
+        let agent = thread::spawn(move || serve_at(&serve_path, DEFAULT_TIMEOUT, reap_interval).unwrap());
+        for _ in 0..100 {
+            if UnixStream::connect(&path).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        (path, agent)
+    }
+
+    fn add_line(name: &str, key: &[u8; 32], timeout: Option<u64>) -> String {
+        let line = format!("ADD {name} {}", BASE64_STANDARD.encode(key));
+        match timeout {
+          Some(timeout) => format!("{line} {timeout}"),
+          None => line,
+        }
+    }
+
+    #[test]
+    fn protocol_roundtrip() {
+      let (path, agent) = start_agent("roundtrip", Duration::from_secs(1));
+      let (work, home) = ([7u8; 32], [9u8; 32]);
+      assert_eq!(request_at(&path, &add_line("work", &work, None), &[]).unwrap(), ["OK"]);
+      assert_eq!(request_at(&path, &add_line("home", &home, None), &[]).unwrap(), ["OK"]);
+      assert_eq!(request_at(&path, "ADD short AAAA", &[]).unwrap(), ["ERR key must be 32 bytes"]);
+      assert_eq!(parse_key(request_at(&path, "GET work", &[]).unwrap()), Some(work));
+
+      let listed = request_at(&path, "LIST", &[]).unwrap();
+      assert_eq!(listed.last().map(String::as_str), Some("END"));
+      let mut names: Vec<&str> = listed.iter().filter_map(|l| l.strip_prefix("ENTRY ")?.split(' ').next()).collect();
+      names.sort();
+      assert_eq!(names, ["home", "work"]);
+      assert!(listed.iter().any(|l| l.starts_with(&format!("ENTRY work {} ", fingerprint(&work)))));
+
+      let data = b"some ciphertext";
+      let expected = enchanter::validation_string(&home, data);
+      assert_eq!(parse_key(request_at(&path, &format!("FIND {expected}"), data).unwrap()), Some(home));
+      assert_eq!(request_at(&path, &format!("FIND {expected}"), b"other").unwrap(), ["ERR no matching key"]);
+
+      assert_eq!(request_at(&path, "FORGET work", &[]).unwrap(), ["OK"]);
+      assert_eq!(request_at(&path, "GET work", &[]).unwrap(), ["ERR no such key"]);
+      assert_eq!(request_at(&path, "FORGET work", &[]).unwrap(), ["ERR no such key"]);
+      assert_eq!(request_at(&path, "FORGETALL", &[]).unwrap(), ["OK"]);
+      assert_eq!(request_at(&path, "LIST", &[]).unwrap(), ["END"]);
+
+      assert_eq!(request_at(&path, "STOP", &[]).unwrap(), ["OK"]);
+      agent.join().unwrap();
+      assert!(!path.exists());
+      assert!(request_at(&path, "LIST", &[]).is_err());
+      let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn idle_keys_are_dropped() {
+      let (path, agent) = start_agent("timeout", Duration::from_millis(50));
+      assert_eq!(request_at(&path, &add_line("brief", &[1u8; 32], Some(1)), &[]).unwrap(), ["OK"]);
+      assert_eq!(request_at(&path, &add_line("lasting", &[2u8; 32], Some(600)), &[]).unwrap(), ["OK"]);
+      assert_eq!(parse_key(request_at(&path, "GET brief", &[]).unwrap()), Some([1u8; 32]));
+      thread::sleep(Duration::from_millis(1300));
+      assert_eq!(request_at(&path, "GET brief", &[]).unwrap(), ["ERR no such key"]);
+      assert_eq!(parse_key(request_at(&path, "GET lasting", &[]).unwrap()), Some([2u8; 32]));
+      assert_eq!(request_at(&path, "STOP", &[]).unwrap(), ["OK"]);
+      agent.join().unwrap();
+      let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,44 @@
+//! Failures of the enchanter command that the library never returns, with their stable error
+//! codes and exit codes. Every other failure is an `enchanter::Error`.
+//!
+//! | exit code | error code           | meaning                                                        |
+//! |-----------|----------------------|----------------------------------------------------------------|
+//! | 9         | `E_KEYFILE`          | the file_password.toml is missing, insecure, or unusable       |
+//! | 10        | `E_WEAK_PASSWORD`    | the password is below the ENCHANTER_MIN_SCORE policy           |
+
+use std::fmt;
+
+/// A failure of the command itself. Each variant carries a human readable message.
+#[derive(Debug)]
+pub enum CliError {
+    Keyfile(String),
+    WeakPassword(String),
+}
+
+impl CliError {
+    /// The stable error identifier.
+    pub fn code(&self) -> &'static str {
+        match self {
+          CliError::Keyfile(_) => "E_KEYFILE",
+          CliError::WeakPassword(_) => "E_WEAK_PASSWORD",
+        }
+    }
+
+    /// The process exit code.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+          CliError::Keyfile(_) => 9,
+          CliError::WeakPassword(_) => 10,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+          CliError::Keyfile(m) | CliError::WeakPassword(m) => write!(f, "{m}"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
@@ -0,0 +1,130 @@
+//! Password generation from the operating system RNG.
+//!
+//! Passphrases are drawn from the embedded BIP-39 English wordlist (2048 words, 11 bits per word),
+//! and random passwords from the printable ASCII characters except `"` and `\`, so that the result
+//! can always be written into a file_password.toml as is.
+
+use rand::rngs::OsRng;
+use rand::{Rng, TryRngCore};
+use zeroize::Zeroize;
+
+use std::error::Error as StdError;
+use std::fs::OpenOptions;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use enchanter::Error;
+
+/// The embedded wordlist, one word per line.
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+/// The default number of words in a passphrase, about 77 bits.
+pub const DEFAULT_WORDS: usize = 7;
+
+/// Characters used for random passwords.
+fn charset() -> Vec<char> {
+    ('!'..='~').filter(|c| *c != '"' && *c != '\\').collect()
+}
+
+/// How a password should be generated.
+pub enum Style {
+    Words(usize, String),
+    Chars(usize),
+}
+
+/// A generated password and its entropy.
+pub struct Generated {
+    pub password: String,
+    pub entropy_bits: f64,
+}
+
+impl Drop for Generated {
+    fn drop(&mut self) {
+        self.password.zeroize();
+    }
+}
+
+/// Generate a password with uniformly chosen words or characters from OsRng.
+pub fn generate(style: &Style) -> Generated {
+    let mut rng = OsRng.unwrap_err();
+    match style {
+        Style::Words(count, separator) => {
+            let words: Vec<&str> = WORDLIST.lines().collect();
+            let chosen: Vec<&str> = (0..*count).map(|_| words[rng.random_range(0..words.len())]).collect();
+            Generated { password: chosen.join(separator), entropy_bits: *count as f64 * (words.len() as f64).log2() }
+        },
+        Style::Chars(count) => {
+            let chars = charset();
+            let password: String = (0..*count).map(|_| chars[rng.random_range(0..chars.len())]).collect();
+            Generated { password, entropy_bits: *count as f64 * (chars.len() as f64).log2() }
+        },
+    }
+}
+
+/// Parse the generation options shared by `genpass` and `encrypt --generate-password`,
+/// returning the style and any arguments that were not generation options.
+pub fn parse_style(args: &[String]) -> Result<(Style, Vec<String>), Box<dyn StdError>> {
+    let mut style = Style::Words(DEFAULT_WORDS, "-".to_string());
+    let mut rest = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--words" => {
+                let value = iter.next().ok_or_else(|| Error::Usage("--words requires a number".into()))?;
+                let separator = match &style { Style::Words(_, s) => s.clone(), Style::Chars(_) => "-".to_string() };
+                style = Style::Words(value.parse().map_err(|_| Error::Usage(format!("Invalid word count: {value}")))?, separator);
+            },
+            "--chars" => {
+                let value = iter.next().ok_or_else(|| Error::Usage("--chars requires a number".into()))?;
+                style = Style::Chars(value.parse().map_err(|_| Error::Usage(format!("Invalid character count: {value}")))?);
+            },
+            "--separator" => {
+                let value = iter.next().ok_or_else(|| Error::Usage("--separator requires a value".into()))?;
+                if value.contains(['"', '\\']) {
+                    return Err(Error::Usage("The separator must not contain double quotes or backslashes".into()).into());
+                }
+                if let Style::Words(count, _) = style {
+                    style = Style::Words(count, value.clone());
+                }
+            },
+            _ => rest.push(arg.clone()),
+        }
+    }
+    match style {
+        Style::Words(0, _) | Style::Chars(0) => Err(Error::Usage("A generated password needs at least one word or character".into()).into()),
+        style => Ok((style, rest)),
+    }
+}
+
+/// Write the password to a new file_password.toml that only the owner can read.
+/// An existing file is never overwritten.
+pub fn write_keyfile(path: &str, password: &str) -> Result<(), Box<dyn StdError>> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).map_err(|e| Error::Io(format!("Failed to create {path}: {e}")))?;
+    let mut contents = format!("enchanter_password = \"{password}\"\n");
+    let result = file.write_all(contents.as_bytes());
+    contents.zeroize();
+    result?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_passwords() {
+      assert_eq!(WORDLIST.lines().count(), 2048);
+      let words = generate(&Style::Words(6, " ".to_string()));
+      assert_eq!(words.password.split(' ').count(), 6);
+      assert_eq!(words.entropy_bits, 66.0);
+      let chars = generate(&Style::Chars(20));
+      assert_eq!(chars.password.chars().count(), 20);
+      assert!(!chars.password.contains(['"', '\\', ' ']));
+      assert_ne!(chars.password, generate(&Style::Chars(20)).password);
+    }
+}
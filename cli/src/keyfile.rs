@@ -0,0 +1,221 @@
+//! Locating, checking and reading the optional file_password.toml key material file.
+//!
+//! The keyfile is looked up in this order:
+//!   1. an explicit path given with --keyfile
+//!   2. $XDG_CONFIG_HOME/enchanter/file_password.toml (or ~/.config/enchanter/file_password.toml)
+//!   3. ./file_password.toml in the working directory
+//!
+//! On unix, a keyfile that is accessible by group or others, or owned by another user,
+//! is refused unless --insecure-keyfile is given.
+//!
+//! Besides the single `enchanter_password`, a keyfile can hold several named passwords
+//! that are selected with --key-name:
+//!
+//! ```toml
+//! enchanter_password = "the default password"
+//!
+//! [passwords]
+//! backups = "another password"
+//! logs = "yet another password"
+//! ```
+
+use serde::Deserialize;
+use zeroize::Zeroize;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::error::CliError;
+
+/// The file name looked for in the config directory and the working directory.
+pub const FILE_NAME: &str = "file_password.toml";
+
+/// The Keyfile struct is optionally used, parsed from file_password.toml.
+#[derive(Deserialize)]
+struct Keyfile {
+    enchanter_password: Option<String>,
+    #[serde(default)]
+    passwords: HashMap<String, String>,
+}
+
+impl Drop for Keyfile {
+    fn drop(&mut self) {
+        self.enchanter_password.zeroize();
+        for password in self.passwords.values_mut() {
+            password.zeroize();
+        }
+    }
+}
+
+/// The enchanter directory in the XDG config home.
+fn config_dir() -> Option<PathBuf> {
+    match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("enchanter")),
+        _ => env::var("HOME").ok().map(|home| PathBuf::from(home).join(".config").join("enchanter")),
+    }
+}
+
+/// Find the keyfile to use, if any. An explicit path that does not exist is an error.
+pub fn locate(explicit: Option<&str>) -> Result<Option<PathBuf>, CliError> {
+    locate_in(explicit, config_dir(), Path::new("./"))
+}
+
+/// Find the keyfile given the config directory and working directory to look in.
+fn locate_in(explicit: Option<&str>, config_dir: Option<PathBuf>, cwd: &Path) -> Result<Option<PathBuf>, CliError> {
+    if let Some(path) = explicit {
+        let path = PathBuf::from(path);
+        if !path.exists() {
+            return Err(CliError::Keyfile(format!("The keyfile {} does not exist", path.display())));
+        }
+        return Ok(Some(path));
+    }
+    let candidates = config_dir.map(|dir| dir.join(FILE_NAME)).into_iter().chain([cwd.join(FILE_NAME)]);
+    for candidate in candidates {
+        if candidate.exists() {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// Refuse keyfiles that other users could read or modify, given the metadata of the open file.
+#[cfg(unix)]
+fn check_permissions(path: &Path, metadata: &fs::Metadata) -> Result<(), CliError> {
+    use std::os::unix::fs::MetadataExt;
+    check_owner_and_mode(path, metadata.uid(), metadata.mode(), unsafe { libc::getuid() })
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path, _metadata: &fs::Metadata) -> Result<(), CliError> {
+    Ok(())
+}
+
+/// Refuse a keyfile owned by a uid other than the current one, or with any group or other permission bits.
+#[cfg(unix)]
+fn check_owner_and_mode(path: &Path, owner: u32, mode: u32, uid: u32) -> Result<(), CliError> {
+    if owner != uid {
+        return Err(CliError::Keyfile(format!(
+            "The keyfile {} is owned by uid {owner} instead of the current user. Pass --insecure-keyfile to use it anyway.",
+            path.display()
+        )));
+    }
+    let mode = mode & 0o777;
+    if mode & 0o077 != 0 {
+        return Err(CliError::Keyfile(format!(
+            "The keyfile {} is accessible by group or others (mode {:o}). Run chmod 600 on it, or pass --insecure-keyfile to use it anyway.",
+            path.display(), mode
+        )));
+    }
+    Ok(())
+}
+
+/// Read the key material bytes from the keyfile, selecting a named password if a name is given.
+/// The file is opened once, and its permissions are checked on the open handle it is read from.
+pub fn read(path: &Path, name: Option<&str>, insecure: bool) -> Result<Vec<u8>, CliError> {
+    let display = path.display();
+    let mut file = File::open(path).map_err(|e| CliError::Keyfile(format!("Failed to open {display}: {e}")))?;
+    if !insecure {
+        let metadata = file.metadata().map_err(|e| CliError::Keyfile(format!("Failed to read {display}: {e}")))?;
+        check_permissions(path, &metadata)?;
+    }
+    let mut kcontents = String::new();
+    if let Err(e) = file.read_to_string(&mut kcontents) {
+        kcontents.zeroize();
+        return Err(CliError::Keyfile(format!("Failed to read {display}: {e}")));
+    }
+    let parsed: Result<Keyfile, _> = toml::from_str(&kcontents);
+    kcontents.zeroize();
+    let kmc = parsed.map_err(|_| CliError::Keyfile(format!("Failed to parse {display}")))?;
+    let password = match name {
+        Some(name) => kmc.passwords.get(name).ok_or_else(|| CliError::Keyfile(format!("No password named {name} in {display}")))?,
+        None => kmc.enchanter_password.as_ref().ok_or_else(|| CliError::Keyfile(format!("No enchanter_password in {display}")))?,
+    };
+    Ok(password.as_bytes().to_vec())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    const CONTENTS: &str = "enchanter_password = \"default\"\n\n[passwords]\nbackups = \"backups password\"\n";
+
+    /// A fresh directory for one test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("enchanter-keyfile-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write a keyfile with the given mode.
+    fn write_keyfile(path: &Path, mode: u32) {
+        fs::write(path, CONTENTS).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn group_and_other_bits_are_refused() {
+      let dir = test_dir("mode");
+      let path = dir.join(FILE_NAME);
+      for mode in [0o640, 0o604, 0o660, 0o644] {
+        write_keyfile(&path, mode);
+        assert_eq!(read(&path, None, false).unwrap_err().code(), "E_KEYFILE");
+      }
+      write_keyfile(&path, 0o600);
+      assert_eq!(read(&path, None, false).unwrap(), b"default");
+      let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn other_owners_are_refused() {
+      let path = Path::new(FILE_NAME);
+      let uid = unsafe { libc::getuid() };
+      assert!(check_owner_and_mode(path, uid, 0o100600, uid).is_ok());
+      let refused = check_owner_and_mode(path, uid + 1, 0o100600, uid).unwrap_err();
+      assert_eq!(refused.code(), "E_KEYFILE");
+      assert!(refused.to_string().contains(&format!("owned by uid {}", uid + 1)));
+    }
+
+    #[test]
+    fn insecure_keyfile_overrides_the_refusal() {
+      let dir = test_dir("insecure");
+      let path = dir.join(FILE_NAME);
+      write_keyfile(&path, 0o644);
+      assert!(read(&path, None, false).is_err());
+      assert_eq!(read(&path, None, true).unwrap(), b"default");
+      let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn config_dir_comes_before_the_working_directory() {
+      let dir = test_dir("lookup");
+      let (config, cwd) = (dir.join("config"), dir.join("cwd"));
+      fs::create_dir_all(&config).unwrap();
+      fs::create_dir_all(&cwd).unwrap();
+      assert_eq!(locate_in(None, Some(config.clone()), &cwd).unwrap(), None);
+      write_keyfile(&cwd.join(FILE_NAME), 0o600);
+      assert_eq!(locate_in(None, Some(config.clone()), &cwd).unwrap(), Some(cwd.join(FILE_NAME)));
+      write_keyfile(&config.join(FILE_NAME), 0o600);
+      assert_eq!(locate_in(None, Some(config.clone()), &cwd).unwrap(), Some(config.join(FILE_NAME)));
+      let explicit = dir.join("explicit.toml");
+      write_keyfile(&explicit, 0o600);
+      assert_eq!(locate_in(explicit.to_str(), Some(config), &cwd).unwrap(), Some(explicit));
+      assert!(locate_in(dir.join("missing.toml").to_str(), None, &cwd).is_err());
+      let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn key_name_selects_a_password() {
+      let dir = test_dir("names");
+      let path = dir.join(FILE_NAME);
+      write_keyfile(&path, 0o600);
+      assert_eq!(read(&path, Some("backups"), false).unwrap(), b"backups password");
+      let unknown = read(&path, Some("logs"), false).unwrap_err();
+      assert!(unknown.to_string().contains("No password named logs"));
+      let _ = fs::remove_dir_all(&dir);
+    }
+}
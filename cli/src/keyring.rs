@@ -0,0 +1,221 @@
+//! Optional caching of the a3 derived key in the Linux kernel keyring.
+//!
+//! When the ENCHANTER_KEYRING environment variable is set to "session" or "user",
+//! the 32 byte key is stored as a "user" type key in that keyring after it has been derived,
+//! with a timeout from ENCHANTER_KEYRING_TIMEOUT (default 900 seconds). Later invocations
+//! in the same login session reuse it to decrypt with -d and -do without prompting or running
+//! Argon2 again. The cached key is never used for encryption, and -de and -deo take the
+//! password from ENC instead. `enchanter forget` revokes the cached key.
+//!
+//! The keyctl calls are behind the `Keyring` trait, so the caching rules can be tested without
+//! a kernel keyring.
+
+use std::env;
+use std::ffi::CString;
+use std::io;
+
+/// The description the key is stored under in the keyring.
+const DESCRIPTION: &str = "enchanter:a3:default";
+
+/// The default timeout for the cached key, in seconds.
+const DEFAULT_TIMEOUT: u32 = 900;
+
+/// Special keyring ids and keyctl operations from linux/keyctl.h.
+const KEY_SPEC_SESSION_KEYRING: libc::c_long = -3;
+const KEY_SPEC_USER_KEYRING: libc::c_long = -4;
+const KEYCTL_REVOKE: libc::c_long = 3;
+const KEYCTL_SEARCH: libc::c_long = 10;
+const KEYCTL_READ: libc::c_long = 11;
+const KEYCTL_SET_TIMEOUT: libc::c_long = 15;
+
+/// The keyrings the key can be cached in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Ring {
+    Session,
+    User,
+}
+
+/// The operations on "user" type keys that the cache needs.
+pub trait Keyring {
+    /// Add or replace the key with the description in the ring, expiring after the timeout in seconds.
+    fn add(&self, ring: Ring, description: &str, key: &[u8; 32], timeout: u32) -> io::Result<()>;
+    /// Read the key with the description from the ring, if it is there and still valid.
+    fn read(&self, ring: Ring, description: &str) -> Option<[u8; 32]>;
+    /// Revoke the key with the description in the ring. Returns false if there was none.
+    fn revoke(&self, ring: Ring, description: &str) -> io::Result<bool>;
+}
+
+/// The Linux kernel keyring, through the add_key and keyctl system calls.
+pub struct Kernel;
+
+impl Kernel {
+    fn id(ring: Ring) -> libc::c_long {
+        match ring {
+          Ring::Session => KEY_SPEC_SESSION_KEYRING,
+          Ring::User => KEY_SPEC_USER_KEYRING,
+        }
+    }
+
+    /// Find the serial number of a key in the given keyring.
+    fn search(ring: Ring, description: &str) -> io::Result<libc::c_long> {
+        let key_type = CString::new("user")?;
+        let description = CString::new(description)?;
+        let id = unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_SEARCH, Kernel::id(ring), key_type.as_ptr(), description.as_ptr(), 0 as libc::c_long) };
+        if id < 0 { Err(io::Error::last_os_error()) } else { Ok(id) }
+    }
+}
+
+impl Keyring for Kernel {
+    fn add(&self, ring: Ring, description: &str, key: &[u8; 32], timeout: u32) -> io::Result<()> {
+        let key_type = CString::new("user")?;
+        let description = CString::new(description)?;
+        let id = unsafe { libc::syscall(libc::SYS_add_key, key_type.as_ptr(), description.as_ptr(), key.as_ptr(), key.len(), Kernel::id(ring)) };
+        if id < 0 {
+          return Err(io::Error::last_os_error());
+        }
+        let rc = unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_SET_TIMEOUT, id, timeout as libc::c_long) };
+        if rc < 0 {
+          return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn read(&self, ring: Ring, description: &str) -> Option<[u8; 32]> {
+        let id = Kernel::search(ring, description).ok()?;
+        let mut key = [0u8; 32];
+        let len = unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_READ, id, key.as_mut_ptr(), key.len()) };
+        if len == 32 { Some(key) } else { None }
+    }
+
+    fn revoke(&self, ring: Ring, description: &str) -> io::Result<bool> {
+        let Ok(id) = Kernel::search(ring, description) else {
+          return Ok(false);
+        };
+        let rc = unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_REVOKE, id) };
+        if rc < 0 {
+          return Err(io::Error::last_os_error());
+        }
+        Ok(true)
+    }
+}
+
+/// The keyring named by an ENCHANTER_KEYRING value, if any.
+fn parse_ring(value: &str) -> Option<Ring> {
+    match value {
+      "session" => Some(Ring::Session),
+      "user" => Some(Ring::User),
+      _ => None,
+    }
+}
+
+/// The keyring selected with ENCHANTER_KEYRING, if any.
+fn selected() -> Option<Ring> {
+    parse_ring(&env::var("ENCHANTER_KEYRING").ok()?)
+}
+
+/// The timeout selected with ENCHANTER_KEYRING_TIMEOUT, or the default.
+fn timeout() -> u32 {
+    env::var("ENCHANTER_KEYRING_TIMEOUT").ok().and_then(|t| t.parse().ok()).unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// Store the key in the configured keyring. Does nothing if ENCHANTER_KEYRING is not set.
+pub fn store(key: &[u8; 32]) -> io::Result<()> {
+    store_in(&Kernel, selected(), key, timeout())
+}
+
+/// Load the key from the configured keyring, if one is cached and still valid.
+pub fn load() -> Option<[u8; 32]> {
+    load_from(&Kernel, selected())
+}
+
+/// Revoke the cached key in both the session and user keyrings.
+/// Returns true if a key was found and revoked.
+pub fn forget() -> io::Result<bool> {
+    forget_in(&Kernel)
+}
+
+fn store_in(keyring: &impl Keyring, ring: Option<Ring>, key: &[u8; 32], timeout: u32) -> io::Result<()> {
+    match ring {
+      Some(ring) => keyring.add(ring, DESCRIPTION, key, timeout),
+      None => Ok(()),
+    }
+}
+
+fn load_from(keyring: &impl Keyring, ring: Option<Ring>) -> Option<[u8; 32]> {
+    keyring.read(ring?, DESCRIPTION)
+}
+
+fn forget_in(keyring: &impl Keyring) -> io::Result<bool> {
+    let mut revoked = false;
+    for ring in [Ring::Session, Ring::User] {
+      revoked |= keyring.revoke(ring, DESCRIPTION)?;
+    }
+    Ok(revoked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    /// The keys in a fake keyring, with the timeout each one was added with.
+    type Keys = HashMap<(Ring, String), ([u8; 32], u32)>;
+
+    /// A keyring in memory.
+    #[derive(Default)]
+    struct Fake {
+        keys: RefCell<Keys>,
+    }
+
+    impl Keyring for Fake {
+        fn add(&self, ring: Ring, description: &str, key: &[u8; 32], timeout: u32) -> io::Result<()> {
+            self.keys.borrow_mut().insert((ring, description.to_string()), (*key, timeout));
+            Ok(())
+        }
+
+        fn read(&self, ring: Ring, description: &str) -> Option<[u8; 32]> {
+            self.keys.borrow().get(&(ring, description.to_string())).map(|(key, _)| *key)
+        }
+
+        fn revoke(&self, ring: Ring, description: &str) -> io::Result<bool> {
+            Ok(self.keys.borrow_mut().remove(&(ring, description.to_string())).is_some())
+        }
+    }
+
+    #[test]
+    fn keys_are_cached_in_the_selected_ring() {
+      let fake = Fake::default();
+      store_in(&fake, None, &[1u8; 32], 900).unwrap();
+      assert!(fake.keys.borrow().is_empty());
+      assert_eq!(load_from(&fake, None), None);
+
+      store_in(&fake, parse_ring("session"), &[2u8; 32], 60).unwrap();
+      assert_eq!(fake.keys.borrow().get(&(Ring::Session, DESCRIPTION.to_string())), Some(&([2u8; 32], 60)));
+      assert_eq!(load_from(&fake, Some(Ring::Session)), Some([2u8; 32]));
+      assert_eq!(load_from(&fake, Some(Ring::User)), None);
+
+      // A later key replaces the cached one.
+      store_in(&fake, Some(Ring::Session), &[3u8; 32], 60).unwrap();
+      assert_eq!(load_from(&fake, Some(Ring::Session)), Some([3u8; 32]));
+    }
+
+    #[test]
+    fn forget_revokes_both_rings() {
+      let fake = Fake::default();
+      assert!(!forget_in(&fake).unwrap());
+      store_in(&fake, Some(Ring::Session), &[4u8; 32], 900).unwrap();
+      store_in(&fake, Some(Ring::User), &[5u8; 32], 900).unwrap();
+      assert!(forget_in(&fake).unwrap());
+      assert!(fake.keys.borrow().is_empty());
+      assert!(!forget_in(&fake).unwrap());
+    }
+
+    #[test]
+    fn ring_names() {
+      assert_eq!(parse_ring("session"), Some(Ring::Session));
+      assert_eq!(parse_ring("user"), Some(Ring::User));
+      assert_eq!(parse_ring(""), None);
+      assert_eq!(parse_ring("process"), None);
+    }
+}
@@ -0,0 +1,925 @@
+use rpassword::read_password;
+use zeroize::Zeroize;
+
+use std::env;
+use std::error::Error as StdError;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+
+mod error;
+mod pinentry;
+mod strength;
+mod genpass;
+mod keyfile;
+mod output;
+#[cfg(unix)]
+mod agent;
+#[cfg(target_os = "linux")]
+mod keyring;
+use enchanter::{age, cose, enchantress, jwe, manifest};
+use enchanter::{CipherSuite, Encrypted, Error, Format, TUR};
+use enchanter::manifest::Manifest;
+use crate::error::CliError;
+use crate::output::Report;
+
+/// The classic mode flags, given after the input and output files.
+const FLAGS: &[&str] = &["-d", "-e", "-ee", "-do", "-de", "-deo"];
+
+/// What a decryption reads: the whole file, validated against enchanter.toml, a chunked file
+/// validated the same way but read a batch of chunks at a time, a JWE token validated the same
+/// way, an enchantress file validated against enchantress.toml, or only the chunks covering a --range.
+enum Ciphertext {
+    Whole { data: Vec<u8>, config: Manifest },
+    Chunked { config: Manifest },
+    Jwe { token: String, alg: jwe::Alg, config: Manifest },
+    Enchantress { data: Vec<u8>, config: enchantress::Manifest },
+    Range { offset: u64, len: u64 },
+}
+
+impl Ciphertext {
+    /// Read what the decryption needs before any key is derived, so malformed input fails early.
+    fn load(input_file: &str, options: &Options) -> Result<Ciphertext, Box<dyn StdError>> {
+        match options.range {
+          Some((offset, len)) => Ok(Ciphertext::Range { offset, len }),
+          None => {
+            // Without an enchanter.toml, an enchantress.toml means the file came from enchantress.
+            let config = match read_config() {
+              Err(Error::ManifestMissing(_)) if Path::new(enchantress::MANIFEST_FILE_NAME).exists() => {
+                let config = enchantress::Manifest::read(enchantress::MANIFEST_FILE_NAME)?;
+                let data = enchanter::read_ciphertext(input_file)?;
+                return Ok(Ciphertext::Enchantress { data, config });
+              },
+              config => config?,
+            };
+            if enchanter::is_chunked_file(input_file)? {
+              return Ok(Ciphertext::Chunked { config });
+            }
+            let data = enchanter::read_ciphertext(input_file)?;
+            if jwe::is_jwe(&data) {
+              let token = String::from_utf8(data).map_err(|_| Error::Malformed("A JWE token is ASCII".into()))?;
+              let alg = jwe::alg(&token)?;
+              return Ok(Ciphertext::Jwe { token, alg, config });
+            }
+            enchanter::parse_any(&data)?;
+            Ok(Ciphertext::Whole { data, config })
+          },
+        }
+    }
+
+    /// Derive the key from password bytes with a3, with a2 for an enchantress file, or by
+    /// unwrapping the content key of a PBES2 JWE.
+    fn derive_key(&self, mut password: Vec<u8>) -> Result<[u8; 32], Error> {
+        match self {
+          Ciphertext::Jwe { token, alg: jwe::Alg::Pbes2, .. } => {
+            let key = jwe::content_key(token, jwe::JweKey::Password(&password));
+            password.zeroize();
+            key
+          },
+          Ciphertext::Enchantress { .. } => {
+            let key = enchantress::derive_key(&password);
+            password.zeroize();
+            key
+          },
+          _ => derive_key(password),
+        }
+    }
+
+    /// Whether a key that validates is an a3 key, which may be cached for later encryptions.
+    fn cacheable(&self) -> bool {
+        !matches!(self, Ciphertext::Enchantress { .. } | Ciphertext::Jwe { alg: jwe::Alg::Pbes2, .. })
+    }
+
+    /// The bytes a validation string is computed over and the expected one from the manifest,
+    /// for a3 keys. Range decryptions, PBES2 tokens and enchantress files have none, and chunked
+    /// files are hashed as they are read rather than sent to the agent whole.
+    fn validation_data(&self) -> Option<(&[u8], &str)> {
+        match self {
+          Ciphertext::Whole { data, config } => Some((data, &config.ciphertext_hash)),
+          Ciphertext::Jwe { token, config, alg: jwe::Alg::Dir } => Some((token.as_bytes(), &config.ciphertext_hash)),
+          _ => None,
+        }
+    }
+
+    /// Whether the key is the right one: its validation string matches enchanter.toml,
+    /// or the first chunk of the range authenticates.
+    fn accepts(&self, input_file: &str, key: &[u8; 32]) -> bool {
+        match self {
+          Ciphertext::Whole { data, config } => enchanter::validation_string(key, data) == config.ciphertext_hash,
+          Ciphertext::Chunked { config } => enchanter::file_validation_string(input_file, key).is_ok_and(|found| found == config.ciphertext_hash),
+          Ciphertext::Jwe { token, config, .. } => enchanter::validation_string(key, token.as_bytes()) == config.ciphertext_hash,
+          Ciphertext::Enchantress { data, config } => enchanter::validation_string(key, data) == config.ciphertext_hash,
+          Ciphertext::Range { offset, .. } => enchanter::decrypt_range(input_file, *offset, 1, key).is_ok(),
+        }
+    }
+
+    /// Decrypt to the output file, or to STDOUT when no output file is given.
+    fn decrypt(&self, input_file: &str, output_file: Option<&str>, key: &[u8], options: &Options) -> Result<(), Box<dyn StdError>> {
+        match self {
+          Ciphertext::Whole { data, config } => {
+            let decrypted = enchanter::decrypt_validated(data, key, Some(&config.ciphertext_hash), options.threads())?;
+            write_decrypted(input_file, output_file, &decrypted.plaintext, &decrypted.validation_string, "")
+          },
+          Ciphertext::Chunked { config } => {
+            let expected = Some(config.ciphertext_hash.as_str());
+            let validation_string = match output_file {
+              Some(output_file) => enchanter::decrypt_file_validated(input_file, output_file, key, expected, options.threads())?,
+              None => enchanter::decrypt_chunked_file(input_file, &mut io::stdout().lock(), key, expected, options.threads())?,
+            };
+            let message = if output_file.is_some() { "file decrypted" } else { "decrypted to STDOUT" };
+            let mut report = Report::ok("decrypt").paths(input_file, output_file).message(message);
+            report.validation_string = Some(validation_string);
+            match output_file {
+              Some(_) => report.emit(),
+              // STDOUT carries the plaintext, so the report goes to STDERR.
+              None => report.emit_stderr(),
+            }
+            Ok(())
+          },
+          Ciphertext::Jwe { token, config, .. } => {
+            let validation_string = enchanter::validation_string(key, token.as_bytes());
+            enchanter::validate(&validation_string, &config.ciphertext_hash)?;
+            let mut plaintext = jwe::decrypt_with_content_key(token, key)?;
+            let result = write_decrypted(input_file, output_file, &plaintext, &validation_string, "JWE ");
+            plaintext.zeroize();
+            result
+          },
+          Ciphertext::Enchantress { data, config } => {
+            let mode = config.mode()?;
+            let decrypted = enchantress::decrypt_validated(data, key, &config.ciphertext_hash, mode)?;
+            if mode == enchantress::Mode::Gcm {
+              Report::warning("decrypt", "enchantress did not encrypt GCM mode files, so this file was stored as plaintext").emit();
+            }
+            write_decrypted(input_file, output_file, &decrypted.plaintext, &decrypted.validation_string, "enchantress ")
+          },
+          Ciphertext::Range { offset, len } => decrypt_range(input_file, output_file, key, *offset, *len),
+        }
+    }
+}
+
+/// Options that can follow the flag in the classic "<input_file> <output_file> <flag>" form.
+#[derive(Default)]
+struct Options {
+    keyfile: Option<String>,
+    key_name: Option<String>,
+    insecure_keyfile: bool,
+    chunked: bool,
+    chunk_size: Option<u32>,
+    threads: Option<usize>,
+    suite: CipherSuite,
+    range: Option<(u64, u64)>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, Box<dyn StdError>> {
+        let mut options = Options::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+          match arg.as_str() {
+            "--keyfile" => options.keyfile = Some(iter.next().ok_or_else(|| Error::Usage("--keyfile requires a path".into()))?.clone()),
+            "--key-name" => options.key_name = Some(iter.next().ok_or_else(|| Error::Usage("--key-name requires a name".into()))?.clone()),
+            "--insecure-keyfile" => options.insecure_keyfile = true,
+            "--range" => {
+              let value = iter.next().ok_or_else(|| Error::Usage("--range requires OFFSET:LENGTH".into()))?;
+              let parsed = value.split_once(':').and_then(|(offset, len)| Some((offset.parse().ok()?, len.parse().ok()?)));
+              options.range = Some(parsed.ok_or_else(|| Error::Usage(format!("Invalid range {value}, expected OFFSET:LENGTH in bytes")))?);
+            },
+            other if options.parse_format(other, &mut iter)? => {},
+            other => return Err(Error::Usage(format!("Unknown option: {other}")).into()),
+          }
+        }
+        Ok(options)
+    }
+
+    /// Handle the format options shared by the classic form and the encrypt subcommand:
+    /// --chunked, --chunk-size BYTES, --threads N and --cipher NAME. Returns false for any other argument.
+    fn parse_format(&mut self, arg: &str, iter: &mut std::slice::Iter<String>) -> Result<bool, Error> {
+        match arg {
+          "--chunked" => self.chunked = true,
+          "--chunk-size" => {
+            let value = iter.next().ok_or_else(|| Error::Usage("--chunk-size requires a number of bytes".into()))?;
+            let size: u32 = value.parse().map_err(|_| Error::Usage(format!("Invalid chunk size: {value}")))?;
+            if size == 0 {
+              return Err(Error::Usage("The chunk size must be at least 1 byte".into()));
+            }
+            self.chunk_size = Some(size);
+          },
+          "--threads" => {
+            let value = iter.next().ok_or_else(|| Error::Usage("--threads requires a number".into()))?;
+            self.threads = Some(value.parse().map_err(|_| Error::Usage(format!("Invalid number of threads: {value}")))?);
+          },
+          "--cipher" => {
+            let value = iter.next().ok_or_else(|| Error::Usage("--cipher requires xchacha20poly1305 or aes-256-gcm-siv".into()))?;
+            self.suite = value.parse()?;
+          },
+          _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The number of threads for chunked files, 1 unless --threads is given.
+    fn threads(&self) -> usize {
+        self.threads.unwrap_or(1)
+    }
+
+    /// The format to encrypt in. Any of the format options, or a cipher other than
+    /// XChaCha20Poly1305, selects the chunked format.
+    fn format(&self) -> Format {
+        if self.chunked || self.chunk_size.is_some() || self.threads.is_some() || self.suite != CipherSuite::XChaCha20Poly1305 {
+          Format::chunked(self.chunk_size.unwrap_or(enchanter::DEFAULT_CHUNK_SIZE)).threads(self.threads())
+        } else {
+          Format::Legacy
+        }
+    }
+}
+
+/// Write a config file each time we encrypt to enchanter.toml.
+fn write_config(ciphertext_path: &str, ciphertext_hash: &str) -> Result<(), Error> {
+    Manifest::new(ciphertext_path, ciphertext_hash).write(manifest::FILE_NAME)
+}
+
+/// Read the required enchanter.toml from the working directory.
+fn read_config() -> Result<Manifest, Error> {
+    Manifest::read(manifest::FILE_NAME).map_err(|e| match e {
+      Error::ManifestMissing(_) => Error::ManifestMissing("No enchanter.toml found in the working directory".into()),
+      e => e,
+    })
+}
+
+/// Read the password from the ENC environment variable.
+fn read_env_password() -> Result<Vec<u8>, Box<dyn StdError>> {
+    let password = env::var("ENC").map_err(|_| Error::Usage("Environment variable ENC not set".into()))?;
+    Ok(password.into_bytes())
+}
+
+/// Prompt for the password with the configured pinentry program, or in the terminal.
+/// With confirm set, the password has to be entered twice. The error text from a
+/// previous failed attempt is shown before prompting again.
+fn prompt_password(description: &str, confirm: bool, error: Option<&str>) -> Result<Vec<u8>, Box<dyn StdError>> {
+    if let Some(program) = pinentry::program() {
+      let prompt = pinentry::Prompt { description, prompt: "Password:", confirm, error };
+      return Ok(pinentry::get_pin(&program, &prompt)?);
+    }
+    if let Some(error) = error {
+      Report::warning(output::operation(), error).emit();
+    }
+    // Hide from STDOUT for output management, use STDERR for password prompt.
+    eprint!("Enter password: ");
+    std::io::stdout().flush()?;
+    let mut password = read_password()?;
+    if confirm {
+      eprint!("Confirm password: ");
+      let mut again = read_password()?;
+      let matched = password == again;
+      again.zeroize();
+      if !matched {
+        password.zeroize();
+        return Err(Error::Usage("Passwords do not match".into()).into());
+      }
+    }
+    Ok(password.into_bytes())
+}
+
+/// Estimate the password strength, warn on STDERR about weak passwords,
+/// and refuse passwords below the ENCHANTER_MIN_SCORE policy (0 to 4, default 0).
+fn check_strength(password: &[u8]) -> Result<(), Box<dyn StdError>> {
+    let minimum: u8 = match env::var("ENCHANTER_MIN_SCORE") {
+      Ok(value) => value.parse().map_err(|_| Error::Usage(format!("Invalid ENCHANTER_MIN_SCORE: {value}")))?,
+      Err(_) => 0,
+    };
+    let estimate = strength::estimate(&String::from_utf8_lossy(password));
+    if estimate.score < 3 {
+      let reason = estimate.warning.map(|w| format!(" {w}")).unwrap_or_default();
+      let message = format!("Weak password, strength score {} of 4 (about 10^{:.0} guesses).{reason}", estimate.score, estimate.guesses_log10);
+      Report::warning(output::operation(), message).emit();
+    }
+    if estimate.score < minimum {
+      return Err(CliError::WeakPassword(format!("Password strength score {} is below the required minimum of {minimum}", estimate.score)).into());
+    }
+    Ok(())
+}
+
+/// Derive an encryption key from a password, after checking it against the strength policy.
+fn derive_encryption_key(password: Vec<u8>) -> Result<[u8; 32], Box<dyn StdError>> {
+    Ok(derive_key(strong_password(password)?)?)
+}
+
+/// Check a password for encryption against the strength policy, zeroizing it if it is refused.
+fn strong_password(mut password: Vec<u8>) -> Result<Vec<u8>, Box<dyn StdError>> {
+    if let Err(e) = check_strength(&password) {
+      password.zeroize();
+      return Err(e);
+    }
+    Ok(password)
+}
+
+/// Prompt for the decryption password, checking each attempt against the enchanter.toml hash
+/// with the ciphertext that has already been read. Up to ENCHANTER_RETRIES attempts (default 3)
+/// are made. The key from the last attempt is returned even if it did not validate, so the
+/// usual refusal is reported.
+fn prompt_decryption_key(input_file: &str, ciphertext: &Ciphertext) -> Result<[u8; 32], Box<dyn StdError>> {
+    let attempts: u32 = env::var("ENCHANTER_RETRIES").ok().and_then(|r| r.parse().ok()).unwrap_or(3).max(1);
+    let description = format!("Enter the password to decrypt {input_file}");
+    let mut error = None;
+    let mut attempt = 1;
+    loop {
+      let mut key = ciphertext.derive_key(prompt_password(&description, false, error.as_deref())?)?;
+      if ciphertext.accepts(input_file, &key) {
+        // Only a3 keys can encrypt, so enchantress and PBES2 keys are not cached.
+        if ciphertext.cacheable() {
+          remember_key(&key);
+        }
+        return Ok(key);
+      }
+      if attempt == attempts {
+        return Ok(key);
+      }
+      key.zeroize();
+      error = Some(format!("Wrong password, {} attempts left.", attempts - attempt));
+      attempt += 1;
+    }
+}
+
+/// Derive the key from password bytes with a3, zeroizing the password afterwards.
+fn derive_key(mut password: Vec<u8>) -> Result<[u8; 32], Error> {
+    let key = enchanter::try_a3(&password, TUR);
+    password.zeroize();
+    key
+}
+
+/// Read the whole ciphertext file for validation, refusing malformed files before asking for a password.
+fn read_ciphertext(input_file: &str) -> Result<Vec<u8>, Error> {
+    let data = enchanter::read_ciphertext(input_file)?;
+    enchanter::parse_any(&data)?;
+    Ok(data)
+}
+
+/// Look for a cached key that validates against the enchanter.toml hash. The agent is asked for
+/// the key matching the validation string, so other cached keys stay in the agent. A --range
+/// decryption has no validation string, so only the kernel keyring key is tried for it.
+fn cached_decryption_key(input_file: &str, ciphertext: &Ciphertext) -> Option<[u8; 32]> {
+    #[cfg(unix)]
+    if let Some((data, expected)) = ciphertext.validation_data() && let Some(key) = agent::find_key(expected, data) {
+      return Some(key);
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(mut key) = keyring::load() {
+      if ciphertext.accepts(input_file, &key) {
+        return Some(key);
+      }
+      key.zeroize();
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = input_file;
+    None
+}
+
+/// Store a freshly derived key in the kernel keyring when ENCHANTER_KEYRING is set.
+fn remember_key(key: &[u8; 32]) {
+    #[cfg(target_os = "linux")]
+    if let Err(e) = keyring::store(key) {
+      Report::warning(output::operation(), format!("Failed to store the key in the kernel keyring: {e}")).emit();
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = key;
+}
+
+/// Write a validated decryption to the output file, or to STDOUT when no output file is given.
+/// The source, such as "enchantress ", starts the report message.
+fn write_decrypted(input_file: &str, output_file: Option<&str>, plaintext: &[u8], validation_string: &str, source: &str) -> Result<(), Box<dyn StdError>> {
+    let message = if output_file.is_some() { format!("{source}file decrypted") } else { format!("{source}decrypted to STDOUT") };
+    let mut report = Report::ok("decrypt").paths(input_file, output_file).message(message);
+    report.validation_string = Some(validation_string.to_string());
+    match output_file {
+      Some(output_file) => {
+        let mut file = File::create(output_file).map_err(|e| Error::Io(format!("Failed to create {output_file}: {e}")))?;
+        file.write_all(plaintext).map_err(|e| Error::Io(format!("Failed to write {output_file}: {e}")))?;
+        report.emit();
+      },
+      None => {
+        println!("{}", String::from_utf8_lossy(plaintext));
+        // STDOUT carries the plaintext, so the report goes to STDERR.
+        report.emit_stderr();
+      }
+    }
+    Ok(())
+}
+
+/// Decrypt only the chunks covering a byte range. The plaintext bytes are written as they are,
+/// to the output file or to STDOUT, and the enchanter.toml is not used.
+fn decrypt_range(input_file: &str, output_file: Option<&str>, key: &[u8], offset: u64, len: u64) -> Result<(), Box<dyn StdError>> {
+    let mut plaintext = enchanter::decrypt_range(input_file, offset, len, key)?;
+    let report = Report::ok("decrypt").paths(input_file, output_file).message(format!("decrypted {} bytes at offset {offset}", plaintext.len()));
+    let written = match output_file {
+      Some(output_file) => File::create(output_file).and_then(|mut file| file.write_all(&plaintext))
+        .map_err(|e| Error::Io(format!("Failed to write {output_file}: {e}"))),
+      None => io::stdout().write_all(&plaintext).and_then(|_| io::stdout().flush())
+        .map_err(|e| Error::Io(format!("Failed to write to STDOUT: {e}"))),
+    };
+    plaintext.zeroize();
+    written?;
+    match output_file {
+      Some(_) => report.emit(),
+      None => report.emit_stderr(),
+    }
+    Ok(())
+}
+
+/// Encrypt the input file in the given format and cipher suite, print the validation string and
+/// record it in enchanter.toml.
+fn encrypt_recorded(input_file: &str, output_file: &str, key: &[u8], format: Format, suite: CipherSuite) -> Result<(), Box<dyn StdError>> {
+    let validate_str = enchanter::encrypt_file_suite(input_file, output_file, key, format, suite)?;
+    let mut report = Report::ok("encrypt").paths(input_file, Some(output_file));
+    if let Err(e) = write_config(output_file, &validate_str) {
+      Report::warning("encrypt", format!("Failed to write enchanter.toml: {e}")).emit();
+    }
+    report.validation_string = Some(validate_str);
+    report.emit();
+    Ok(())
+}
+
+/// Get the encryption key by prompting for a password. Keys cached in the agent or the kernel
+/// keyring are only used for decryption, so a new file always gets a chosen password.
+fn interactive_encryption_key(input_file: &str) -> Result<[u8; 32], Box<dyn StdError>> {
+    let key = derive_encryption_key(prompt_password(&format!("Enter the password to encrypt {input_file}"), true, None)?)?;
+    remember_key(&key);
+    Ok(key)
+}
+
+/// Handle `enchanter genpass [--words N | --chars N] [--separator S]`.
+fn genpass_command(args: &[String]) -> Result<(), Box<dyn StdError>> {
+    let (style, rest) = genpass::parse_style(args)?;
+    if !rest.is_empty() {
+      return Err(usage("Usage: enchanter genpass [--words N | --chars N] [--separator S]"));
+    }
+    let generated = genpass::generate(&style);
+    let mut report = Report::ok("genpass");
+    report.password = Some(generated.password.clone());
+    report.entropy_bits = Some((generated.entropy_bits * 10.0).round() / 10.0);
+    report.emit();
+    report.password.zeroize();
+    Ok(())
+}
+
+/// Handle `enchanter encrypt <input_file> <output_file> [--generate-password [--save-keyfile]
+/// [--words N | --chars N] [--separator S]] [--env] [--format jwe [--alg ALG]]`. Without
+/// --generate-password, --env or --format this is the same as -e.
+fn encrypt_command(args: &[String]) -> Result<(), Box<dyn StdError>> {
+    const ENCRYPT_USAGE: &str = "Usage: enchanter encrypt <input_file> <output_file> [--generate-password [--save-keyfile] [--words N | --chars N] [--separator S]] [--env] [--chunked] [--chunk-size BYTES] [--threads N] [--cipher NAME] [--format jwe [--alg dir|PBES2-HS512+A256KW]]";
+    let (style, rest) = genpass::parse_style(args)?;
+    let mut options = Options::default();
+    let (mut generate, mut save, mut env_password) = (false, false, false);
+    let (mut format, mut alg) = (None, None);
+    let mut files = Vec::new();
+    let mut iter = rest.iter();
+    while let Some(arg) = iter.next() {
+      match arg.as_str() {
+        "--generate-password" => generate = true,
+        "--save-keyfile" => save = true,
+        "--env" => env_password = true,
+        "--format" => format = Some(iter.next().ok_or_else(|| usage(ENCRYPT_USAGE))?.as_str()),
+        "--alg" => alg = Some(iter.next().ok_or_else(|| usage(ENCRYPT_USAGE))?.parse::<jwe::Alg>()?),
+        other if options.parse_format(other, &mut iter)? => {},
+        other if !other.starts_with("--") => files.push(other),
+        _ => return Err(usage(ENCRYPT_USAGE)),
+      }
+    }
+    if files.len() != 2 || (save && !generate) || (generate && env_password) {
+      return Err(usage(ENCRYPT_USAGE));
+    }
+    let jwe = match format {
+      None if alg.is_none() => None,
+      Some("jwe") => Some(alg.unwrap_or_default()),
+      _ => return Err(usage(ENCRYPT_USAGE)),
+    };
+    if jwe.is_some() && (!matches!(options.format(), Format::Legacy) || options.suite != CipherSuite::default()) {
+      return Err(usage("A JWE is a single XC20P token, so --format jwe can't be combined with --chunked, --chunk-size, --threads or --cipher"));
+    }
+    let (input_file, output_file) = (files[0], files[1]);
+
+    let password = if generate {
+      let generated = genpass::generate(&style);
+      if save {
+        genpass::write_keyfile(&format!("./{}", keyfile::FILE_NAME), &generated.password)?;
+      }
+      // The generated password is only ever shown here.
+      let mut report = Report::ok("genpass").message("generated password for encryption");
+      report.password = Some(generated.password.clone());
+      report.entropy_bits = Some((generated.entropy_bits * 10.0).round() / 10.0);
+      report.emit();
+      report.password.zeroize();
+      Some(generated.password.as_bytes().to_vec())
+    } else if env_password {
+      Some(strong_password(read_env_password()?)?)
+    } else if jwe == Some(jwe::Alg::Pbes2) {
+      // PBES2 wraps the key under the password itself, so a cached key can't be used.
+      Some(strong_password(prompt_password(&format!("Enter the password to encrypt {input_file}"), true, None)?)?)
+    } else {
+      None
+    };
+
+    let mut key = match (jwe, password) {
+      (Some(jwe::Alg::Pbes2), Some(mut password)) => {
+        let result = encrypt_jwe(input_file, output_file, jwe::JweKey::Password(&password));
+        password.zeroize();
+        return result;
+      },
+      (_, Some(password)) => derive_key(password)?,
+      (_, None) => interactive_encryption_key(input_file)?,
+    };
+    let result = match jwe {
+      Some(_) => encrypt_jwe(input_file, output_file, jwe::JweKey::Direct(&key)),
+      None => encrypt_recorded(input_file, output_file, &key, options.format(), options.suite),
+    };
+    key.zeroize();
+    result
+}
+
+/// Encrypt the input file into a JWE compact token and record it in enchanter.toml. The validation
+/// string is computed with the token's content key, the a3 key itself for dir.
+fn encrypt_jwe(input_file: &str, output_file: &str, key: jwe::JweKey<'_>) -> Result<(), Box<dyn StdError>> {
+    let mut plaintext = fs::read(input_file).map_err(|e| Error::Io(format!("Failed to read {input_file}: {e}")))?;
+    let token = jwe::encrypt(&plaintext, key);
+    plaintext.zeroize();
+    let token = token?;
+    let mut cek = jwe::content_key(&token, key)?;
+    let validate_str = enchanter::validation_string(&cek, token.as_bytes());
+    cek.zeroize();
+    fs::write(output_file, &token).map_err(|e| Error::Io(format!("Failed to write {output_file}: {e}")))?;
+    let mut report = Report::ok("encrypt").paths(input_file, Some(output_file)).message(format!("encrypted to a JWE with {}", jwe::alg(&token)?.name()));
+    if let Err(e) = write_config(output_file, &validate_str) {
+      Report::warning("encrypt", format!("Failed to write enchanter.toml: {e}")).emit();
+    }
+    report.validation_string = Some(validate_str);
+    report.emit();
+    Ok(())
+}
+
+/// Handle `enchanter import <input_file> <output_file> [--manifest PATH] [--identity PATH] [--env]`,
+/// converting a file written by enchantress, an age v1 file or a COSE_Encrypt0 message to the
+/// enchanter format. The new file is recorded in enchanter.toml.
+fn import_command(args: &[String]) -> Result<(), Box<dyn StdError>> {
+    const IMPORT_USAGE: &str = "Usage: enchanter import <input_file> <output_file> [--manifest PATH] [--identity PATH] [--env] [--chunked] [--chunk-size BYTES] [--threads N] [--cipher NAME]";
+    let mut options = Options::default();
+    let mut manifest_path = enchantress::MANIFEST_FILE_NAME.to_string();
+    let mut identity = None;
+    let mut env_password = false;
+    let mut files = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+      match arg.as_str() {
+        "--manifest" => manifest_path = iter.next().ok_or_else(|| usage(IMPORT_USAGE))?.clone(),
+        "--identity" => identity = Some(iter.next().ok_or_else(|| usage(IMPORT_USAGE))?.as_str()),
+        "--env" => env_password = true,
+        other if options.parse_format(other, &mut iter)? => {},
+        other if !other.starts_with("--") => files.push(other),
+        _ => return Err(usage(IMPORT_USAGE)),
+      }
+    }
+    if files.len() != 2 {
+      return Err(usage(IMPORT_USAGE));
+    }
+    let (input_file, output_file) = (files[0], files[1]);
+    output::set_paths(input_file, Some(output_file));
+
+    let data = enchanter::read_ciphertext(input_file)?;
+    let (imported, message) = if age::is_age(&data) {
+      (import_age(input_file, &data, identity, env_password, &options)?, "age file imported".to_string())
+    } else if cose::is_cose(&data) {
+      (import_cose(input_file, &data, env_password, &options)?, "COSE_Encrypt0 message imported".to_string())
+    } else {
+      let config = enchantress::Manifest::read(&manifest_path)?;
+      let mode = config.mode()?;
+      let imported = import_enchantress(input_file, &data, &config, env_password, &options)?;
+      if mode == enchantress::Mode::Gcm {
+        Report::warning("import", "enchantress did not encrypt GCM mode files, so this file was stored as plaintext").emit();
+      }
+      (imported, format!("enchantress {} file imported", mode.name()))
+    };
+
+    let mut output = File::create(output_file).map_err(|e| Error::Io(format!("Failed to create {output_file}: {e}")))?;
+    output.write_all(&imported.ciphertext).map_err(|e| Error::Io(format!("Failed to write {output_file}: {e}")))?;
+    if let Err(e) = write_config(output_file, &imported.validation_string) {
+      Report::warning("import", format!("Failed to write enchanter.toml: {e}")).emit();
+    }
+    let mut report = Report::ok("import").paths(input_file, Some(output_file)).message(message);
+    report.validation_string = Some(imported.validation_string.clone());
+    report.emit();
+    Ok(())
+}
+
+/// Convert an enchantress file, validated against its enchantress.toml, with the same password for both keys.
+fn import_enchantress(input_file: &str, data: &[u8], config: &enchantress::Manifest, env_password: bool, options: &Options) -> Result<Encrypted, Box<dyn StdError>> {
+    let mut password = if env_password {
+      read_env_password()?
+    } else {
+      prompt_password(&format!("Enter the enchantress password of {input_file}"), false, None)?
+    };
+    // The same password derives the enchantress key with a2 and the enchanter key with a3.
+    let mut from_key = match enchantress::derive_key(&password) {
+      Ok(key) => key,
+      Err(e) => {
+        password.zeroize();
+        return Err(e.into());
+      },
+    };
+    let mut to_key = match derive_encryption_key(password) {
+      Ok(key) => key,
+      Err(e) => {
+        from_key.zeroize();
+        return Err(e);
+      },
+    };
+    let imported = enchantress::import(data, config, &from_key, &to_key, options.format(), options.suite);
+    from_key.zeroize();
+    to_key.zeroize();
+    Ok(imported?)
+}
+
+/// Convert an age file. With an identity file it is opened with the X25519 keys in it and the
+/// enchanter password is asked for as in -e, otherwise the age passphrase is also the enchanter password.
+fn import_age(input_file: &str, data: &[u8], identity: Option<&str>, env_password: bool, options: &Options) -> Result<Encrypted, Box<dyn StdError>> {
+    let (identities, mut key) = match identity {
+      Some(path) => {
+        let contents = fs::read_to_string(path).map_err(|e| Error::Io(format!("Failed to read {path}: {e}")))?;
+        let keys = contents.lines().map(str::trim).filter(|line| line.starts_with("AGE-SECRET-KEY-")).map(str::to_string).collect();
+        let key = if env_password { derive_encryption_key(read_env_password()?)? } else { interactive_encryption_key(input_file)? };
+        (age::Identities::X25519(keys), key)
+      },
+      None => {
+        let password = if env_password {
+          read_env_password()?
+        } else {
+          prompt_password(&format!("Enter the age passphrase of {input_file}"), false, None)?
+        };
+        let passphrase = String::from_utf8(password.clone()).map_err(|_| Error::Usage("The age passphrase is not UTF-8".into()))?;
+        (age::Identities::Passphrase(passphrase), derive_encryption_key(password)?)
+      },
+    };
+    let imported = age::import(data, &identities, &key, options.format(), options.suite);
+    key.zeroize();
+    Ok(imported?)
+}
+
+/// Convert a COSE_Encrypt0 message, opened with the key its KDF headers describe, or a3 with TUR
+/// when it has none. The enchanter key is derived from the same password as in -e.
+fn import_cose(input_file: &str, data: &[u8], env_password: bool, options: &Options) -> Result<Encrypted, Box<dyn StdError>> {
+    let kdf = cose::kdf(data)?.unwrap_or_else(|| cose::Kdf { salt: TUR.to_vec() });
+    let mut password = if env_password {
+      read_env_password()?
+    } else {
+      prompt_password(&format!("Enter the password of {input_file}"), false, None)?
+    };
+    let mut from_key = match kdf.derive_key(&password) {
+      Ok(key) => key,
+      Err(e) => {
+        password.zeroize();
+        return Err(e.into());
+      },
+    };
+    let mut to_key = match derive_encryption_key(password) {
+      Ok(key) => key,
+      Err(e) => {
+        from_key.zeroize();
+        return Err(e);
+      },
+    };
+    let imported = cose::import(data, &from_key, &to_key, options.format(), options.suite);
+    from_key.zeroize();
+    to_key.zeroize();
+    Ok(imported?)
+}
+
+/// Handle `enchanter export <input_file> <output_file> --format age|cose [--recipient KEY]... [--armor] [--env]`.
+/// The enchanter file is validated against enchanter.toml and decrypted in memory. Without a
+/// --recipient, the age file gets an scrypt recipient with the same password. A COSE_Encrypt0
+/// message keeps the enchanter key, with TUR as its KDF salt.
+fn export_command(args: &[String]) -> Result<(), Box<dyn StdError>> {
+    const EXPORT_USAGE: &str = "Usage: enchanter export <input_file> <output_file> --format age|cose [--recipient KEY]... [--armor] [--env] [--keyfile PATH] [--key-name NAME] [--insecure-keyfile]";
+    let mut options = Options::default();
+    let (mut format, mut armor, mut env_password) = (None, false, false);
+    let mut recipients = Vec::new();
+    let mut files = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+      match arg.as_str() {
+        "--format" => format = Some(iter.next().ok_or_else(|| usage(EXPORT_USAGE))?.as_str()),
+        "--recipient" => recipients.push(iter.next().ok_or_else(|| usage(EXPORT_USAGE))?.clone()),
+        "--armor" => armor = true,
+        "--env" => env_password = true,
+        "--keyfile" => options.keyfile = Some(iter.next().ok_or_else(|| usage(EXPORT_USAGE))?.clone()),
+        "--key-name" => options.key_name = Some(iter.next().ok_or_else(|| usage(EXPORT_USAGE))?.clone()),
+        "--insecure-keyfile" => options.insecure_keyfile = true,
+        other if !other.starts_with("--") => files.push(other),
+        _ => return Err(usage(EXPORT_USAGE)),
+      }
+    }
+    if files.len() != 2 || !matches!(format, Some("age" | "cose")) {
+      return Err(usage(EXPORT_USAGE));
+    }
+    if format == Some("cose") && (armor || !recipients.is_empty()) {
+      return Err(usage("A COSE_Encrypt0 message keeps the enchanter key, so --format cose can't be combined with --recipient or --armor"));
+    }
+    let (input_file, output_file) = (files[0], files[1]);
+    output::set_paths(input_file, Some(output_file));
+
+    let config = read_config()?;
+    let data = read_ciphertext(input_file)?;
+    let password = match keyfile::locate(options.keyfile.as_deref())? {
+      Some(keyfile) => keyfile::read(&keyfile, options.key_name.as_deref(), options.insecure_keyfile)?,
+      None if env_password => read_env_password()?,
+      None => prompt_password(&format!("Enter the password to decrypt {input_file}"), false, None)?,
+    };
+    if format == Some("cose") {
+      let mut key = derive_key(password)?;
+      let exported = cose::export(&data, &key, Some(&config.ciphertext_hash), Some(&cose::Kdf { salt: TUR.to_vec() }));
+      key.zeroize();
+      fs::write(output_file, &exported?).map_err(|e| Error::Io(format!("Failed to write {output_file}: {e}")))?;
+      Report::ok("export").paths(input_file, Some(output_file)).message("exported to COSE_Encrypt0").emit();
+      return Ok(());
+    }
+    let (recipients, message) = if recipients.is_empty() {
+      let passphrase = String::from_utf8(password.clone()).map_err(|_| Error::Usage("The password is not UTF-8, so it can't be an age passphrase".into()))?;
+      (age::Recipients::Passphrase(passphrase), "exported to age with a passphrase")
+    } else {
+      (age::Recipients::X25519(recipients), "exported to age for X25519 recipients")
+    };
+    let mut key = derive_key(password)?;
+    let exported = age::export(&data, &key, Some(&config.ciphertext_hash), &recipients, armor);
+    key.zeroize();
+    let exported = exported?;
+
+    fs::write(output_file, &exported).map_err(|e| Error::Io(format!("Failed to write {output_file}: {e}")))?;
+    Report::ok("export").paths(input_file, Some(output_file)).message(message).emit();
+    Ok(())
+}
+
+/// A usage error for the command line.
+fn usage(message: impl Into<String>) -> Box<dyn StdError> {
+    Error::Usage(message.into()).into()
+}
+
+/// The usage for the classic form.
+const USAGE: &str = "Usage: enchanter <input_file> <output_file> < -d, -e, -ee, -do, -de, -deo > [--keyfile PATH] [--key-name NAME] [--insecure-keyfile] [--range OFFSET:LENGTH] [--chunked] [--chunk-size BYTES] [--threads N] [--cipher NAME] [--output json|text]";
+
+/// Which commands use cached keys, shown after the usage by -h and --help.
+const CACHE_SCOPE: &str = "Keys cached in the agent or, with ENCHANTER_KEYRING set, in the kernel keyring are only used by -d and -do, to skip the password prompt when decrypting. \
+-e, -ee, encrypt, -de and -deo never read the cache: encryption always asks for the password, and -de and -deo take it from ENC.";
+
+/// The bulk of "main" is moved to "run" for error handling.
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args: Vec<String> = env::args().collect();
+
+    // --output applies to every command, so it is taken out before anything else is parsed.
+    if let Some(at) = args.iter().position(|a| a == "--output") {
+      let format = match args.get(at + 1).map(String::as_str) {
+        Some("json") => output::Format::Json,
+        Some("text") => output::Format::Text,
+        _ => return Err(usage("--output requires json or text")),
+      };
+      output::set_format(format);
+      args.drain(at..at + 2);
+    }
+
+    if args.len() < 2 {
+      return Err(usage(USAGE));
+    }
+    let input_file = &args[1];
+    if input_file == "-h" || input_file == "--help" {
+      Report::ok("help").message(format!("{USAGE} {CACHE_SCOPE}")).emit();
+      return Ok(());
+    }
+    if input_file == "-v" {
+      let mut report = Report::ok("version");
+      report.version = Some(env!("CARGO_PKG_VERSION"));
+      report.emit();
+      return Ok(());
+    }
+    // Subcommands are only considered when the arguments are not a classic
+    // "<input_file> <output_file> <flag>" invocation, so a file named like a
+    // subcommand can still be encrypted and decrypted.
+    let classic = args.len() >= 4 && FLAGS.contains(&args[3].as_str());
+    if !classic {
+      match input_file.as_str() {
+        #[cfg(unix)]
+        "agent" => {
+          output::set_operation("agent");
+          return agent::command(&args[2..]);
+        },
+        #[cfg(target_os = "linux")]
+        "forget" => {
+          output::set_operation("forget");
+          let message = if keyring::forget()? { "cached key revoked" } else { "no cached key found" };
+          Report::ok("forget").message(message).emit();
+          return Ok(());
+        },
+        "genpass" => {
+          output::set_operation("genpass");
+          return genpass_command(&args[2..]);
+        },
+        "encrypt" => {
+          output::set_operation("encrypt");
+          return encrypt_command(&args[2..]);
+        },
+        "import" => {
+          output::set_operation("import");
+          return import_command(&args[2..]);
+        },
+        "export" => {
+          output::set_operation("export");
+          return export_command(&args[2..]);
+        },
+        _ => {}
+      }
+    }
+    if args.len() < 4 {
+      return Err(usage(USAGE));
+    }
+    let output_file = &args[2];
+    let flag = &args[3];
+    if !FLAGS.contains(&flag.as_str()) {
+      return Err(usage(format!("Invalid flag. Use -d for decryption or -e for encryption of a file using a supplied password. Use -ee to encrypt with an environment variable ENC, and -de to decrypt with an environment variable. Environment variable options are not available if a file_password.toml is in use. Use -do to decrypt to STDOUT, and -deo to use an environment variable and decrypt to STDOUT. Use -v to print the version of enchanter. {USAGE}")));
+    }
+    output::set_operation(if flag.starts_with("-e") { "encrypt" } else { "decrypt" });
+    output::set_paths(input_file, if flag.ends_with('o') { None } else { Some(output_file) });
+    let options = Options::parse(&args[4..])?;
+    if options.range.is_some() && flag.starts_with("-e") {
+      return Err(usage("--range is only available for decryption"));
+    }
+
+    if let Some(keyfile) = keyfile::locate(options.keyfile.as_deref())? {
+      let read_keyfile = || keyfile::read(&keyfile, options.key_name.as_deref(), options.insecure_keyfile);
+
+      match flag.as_str() {
+        "-do" | "-d" => {
+          let ciphertext = Ciphertext::load(input_file, &options)?;
+          let mut key = ciphertext.derive_key(read_keyfile()?)?;
+          let output = if flag == "-d" { Some(output_file.as_str()) } else { None };
+          let result = ciphertext.decrypt(input_file, output, &key, &options);
+          key.zeroize();
+          result?;
+        },
+        "-e" => {
+          let mut key = derive_encryption_key(read_keyfile()?)?;
+          let result = encrypt_recorded(input_file, output_file, &key, options.format(), options.suite);
+          key.zeroize();
+          result?;
+        },
+        _ => {
+          return Err(usage(format!("Environment variable options are not available if a file_password.toml is in use. A file_password.toml has been found at {}.", keyfile.display())));
+        }
+      }
+
+    } else {
+
+      if options.key_name.is_some() {
+        return Err(usage("--key-name selects a password from a file_password.toml, but none was found"));
+      }
+
+      match flag.as_str() {
+        "-deo" | "-de" => {
+          let ciphertext = Ciphertext::load(input_file, &options)?;
+          let mut key = ciphertext.derive_key(read_env_password()?)?;
+          let output = if flag == "-de" { Some(output_file.as_str()) } else { None };
+          let result = ciphertext.decrypt(input_file, output, &key, &options);
+          key.zeroize();
+          result?;
+        },
+        "-do" | "-d" => {
+          let ciphertext = Ciphertext::load(input_file, &options)?;
+          // A cached key skips both the prompt and the Argon2 work.
+          let mut key = match cached_decryption_key(input_file, &ciphertext) {
+            Some(key) => key,
+            None => prompt_decryption_key(input_file, &ciphertext)?,
+          };
+          let output = if flag == "-d" { Some(output_file.as_str()) } else { None };
+          let result = ciphertext.decrypt(input_file, output, &key, &options);
+          key.zeroize();
+          result?;
+        },
+        "-ee" => {
+          let mut key = derive_encryption_key(read_env_password()?)?;
+          let result = encrypt_recorded(input_file, output_file, &key, options.format(), options.suite);
+          key.zeroize();
+          result?;
+        },
+        _ => {
+          let mut key = interactive_encryption_key(input_file)?;
+          let result = encrypt_recorded(input_file, output_file, &key, options.format(), options.suite);
+          key.zeroize();
+          result?;
+        },
+      }
+    }
+
+    Ok(())
+}
+
+/// The main function is a wrapper for the run function, for error catching.
+/// Every error is reported on STDERR in the selected output format.
+fn main() {
+    if let Err(e) = run() {
+        process::exit(output::report_failure(&*e));
+    }
+}
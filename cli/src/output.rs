@@ -0,0 +1,302 @@
+//! Structured output for every command.
+//!
+//! Every result is a `Report` serialized with serde. With `--output json` (the default) a report is
+//! one JSON object per line; with `--output text` the same fields are printed as "Name: value" lines.
+//! Successful reports go to STDOUT, except when STDOUT carries decrypted plaintext, in which case
+//! they go to STDERR. Errors and warnings always go to STDERR.
+//!
+//! Schema version 1 fields, omitted when not relevant:
+//!
+//! | field                        | meaning                                               |
+//! |------------------------------|-------------------------------------------------------|
+//! | `schema`                     | the schema version, currently 1                       |
+//! | `status`                     | `ok`, `error` or `warning`                            |
+//! | `operation`                  | `encrypt`, `decrypt`, `import`, `export`, `genpass`, `agent`, `forget`, `help`, `version` or `unknown` |
+//! | `input`, `output`            | the input and output paths                            |
+//! | `validation_string`          | the validation string (ciphertext_hash) computed      |
+//! | `expected_validation_string` | the ciphertext_hash from enchanter.toml               |
+//! | `error_code`                 | a stable error identifier, for errors                 |
+//! | `message`                    | a human readable description                          |
+//! | `version`                    | the enchanter version                                 |
+//! | `password`, `entropy_bits`   | a generated password and its entropy                  |
+//! | `socket`, `name`, `keys`     | agent details                                         |
+//!
+//! New `operation` values, like new fields, are added within a schema version without bumping it,
+//! so consumers should accept values they do not know.
+
+use serde::Serialize;
+
+use std::sync::OnceLock;
+
+use enchanter::Error;
+
+use crate::error::CliError;
+
+/// The version of the output schema. Fields and operation values may be added within a version, but
+/// not removed or changed.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The output format selected with --output.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Text,
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+static OPERATION: OnceLock<&'static str> = OnceLock::new();
+static PATHS: OnceLock<(String, Option<String>)> = OnceLock::new();
+
+/// Select the output format. Only the first call has an effect.
+pub fn set_format(format: Format) {
+    let _ = FORMAT.set(format);
+}
+
+fn format() -> Format {
+    *FORMAT.get().unwrap_or(&Format::Json)
+}
+
+/// Record the operation being run, so errors returned from it are reported against it.
+pub fn set_operation(operation: &'static str) {
+    let _ = OPERATION.set(operation);
+}
+
+pub fn operation() -> &'static str {
+    OPERATION.get().copied().unwrap_or("unknown")
+}
+
+/// Record the paths of the operation, so errors returned from it include them.
+pub fn set_paths(input: &str, output: Option<&str>) {
+    let _ = PATHS.set((input.to_string(), output.map(str::to_string)));
+}
+
+/// A cached agent key as listed by `enchanter agent list`.
+#[derive(Serialize)]
+pub struct AgentKey {
+    pub name: String,
+    pub fingerprint: String,
+    pub seconds_remaining: u64,
+}
+
+/// The result of an operation.
+#[derive(Serialize, Default)]
+pub struct Report {
+    pub schema: u32,
+    pub status: &'static str,
+    pub operation: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validation_string: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_validation_string: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entropy_bits: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keys: Option<Vec<AgentKey>>,
+}
+
+impl Report {
+    /// A successful report for the operation.
+    pub fn ok(operation: &'static str) -> Report {
+        Report { schema: SCHEMA_VERSION, status: "ok", operation, ..Default::default() }
+    }
+
+    /// An error report for the operation.
+    pub fn error(operation: &'static str, code: &'static str, message: impl Into<String>) -> Report {
+        Report { schema: SCHEMA_VERSION, status: "error", operation, error_code: Some(code), message: Some(message.into()), ..Default::default() }
+    }
+
+    /// A warning about the operation, which carries on.
+    pub fn warning(operation: &'static str, message: impl Into<String>) -> Report {
+        Report { schema: SCHEMA_VERSION, status: "warning", operation, message: Some(message.into()), ..Default::default() }
+    }
+
+    pub fn message(mut self, message: impl Into<String>) -> Report {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn paths(mut self, input: &str, output: Option<&str>) -> Report {
+        self.input = Some(input.to_string());
+        self.output = output.map(str::to_string);
+        self
+    }
+
+    /// Render the report in the given format.
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Json => serde_json::to_string(self).unwrap_or_default(),
+            Format::Text => {
+                let value = serde_json::to_value(self).unwrap_or_default();
+                let mut lines = Vec::new();
+                if let Some(fields) = value.as_object() {
+                    for (field, value) in fields {
+                        if field == "schema" {
+                            continue;
+                        }
+                        let label = field.replace('_', " ");
+                        match value {
+                            serde_json::Value::String(s) => lines.push(format!("{label}: {s}")),
+                            serde_json::Value::Array(items) => {
+                                lines.push(format!("{label}:"));
+                                for item in items {
+                                    let fields = item.as_object().map(|o| o.values().map(|v| v.to_string().trim_matches('"').to_string()).collect::<Vec<_>>().join("  "));
+                                    lines.push(format!("  {}", fields.unwrap_or_default()));
+                                }
+                            },
+                            other => lines.push(format!("{label}: {other}")),
+                        }
+                    }
+                }
+                lines.join("\n")
+            },
+        }
+    }
+
+    /// Print the report: errors and warnings to STDERR, everything else to STDOUT.
+    pub fn emit(&self) {
+        if self.status == "ok" {
+            println!("{}", self.render(format()));
+        } else {
+            eprintln!("{}", self.render(format()));
+        }
+    }
+
+    /// Print the report to STDERR, for operations that write plaintext to STDOUT.
+    pub fn emit_stderr(&self) {
+        eprintln!("{}", self.render(format()));
+    }
+}
+
+/// Report an error returned from a command on STDERR, and return the process exit code for it.
+pub fn report_failure(e: &(dyn std::error::Error + 'static)) -> i32 {
+    let paths = PATHS.get().map(|(input, output)| (input.as_str(), output.as_deref()));
+    let (report, exit_code) = failure(e, operation(), paths);
+    report.emit();
+    exit_code
+}
+
+/// The report and exit code for an error returned from the operation. Errors that are neither an
+/// enchanter Error nor a CliError are reported as E_IO for I/O errors and E_FAILED otherwise.
+fn failure(e: &(dyn std::error::Error + 'static), operation: &'static str, paths: Option<(&str, Option<&str>)>) -> (Report, i32) {
+    let with_paths = |report: Report| match paths {
+        Some((input, output)) => report.paths(input, output),
+        None => report,
+    };
+    if let Some(error) = e.downcast_ref::<CliError>() {
+        return (with_paths(Report::error(operation, error.code(), error.to_string())), error.exit_code());
+    }
+    let fallback;
+    let error = match e.downcast_ref::<Error>() {
+        Some(error) => error,
+        None => {
+            fallback = if e.is::<std::io::Error>() { Error::Io(e.to_string()) } else { Error::Failed(e.to_string()) };
+            &fallback
+        },
+    };
+    let mut report = with_paths(Report::error(operation, error.code(), error.to_string()));
+    if let Error::Mismatch { found, expected } = error {
+        report.validation_string = Some(found.clone());
+        report.expected_validation_string = Some(expected.clone());
+    }
+    (report, error.exit_code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn parse(report: &Report) -> Value {
+        serde_json::from_str(&report.render(Format::Json)).unwrap()
+    }
+
+    #[test]
+    fn schema_one_fields() {
+        assert_eq!(parse(&Report::ok("version")), json!({"schema": 1, "status": "ok", "operation": "version"}));
+
+        let mut report = Report::ok("agent").paths("in.e", Some("out")).message("done");
+        report.validation_string = Some("found".into());
+        report.expected_validation_string = Some("expected".into());
+        report.error_code = Some("E_AUTH");
+        report.version = Some("0.1.3");
+        report.password = Some("correct horse".into());
+        report.entropy_bits = Some(77.5);
+        report.socket = Some("/run/enchanter.sock".into());
+        report.name = Some("home".into());
+        report.keys = Some(vec![AgentKey { name: "home".into(), fingerprint: "abcd".into(), seconds_remaining: 60 }]);
+        let value = parse(&report);
+        let fields: Vec<&str> = value.as_object().unwrap().keys().map(String::as_str).collect();
+        assert_eq!(fields, [
+            "schema", "status", "operation", "input", "output", "validation_string", "expected_validation_string",
+            "error_code", "message", "version", "password", "entropy_bits", "socket", "name", "keys",
+        ]);
+        assert_eq!(value["schema"], json!(1));
+        assert_eq!(value["entropy_bits"], json!(77.5));
+        assert_eq!(value["keys"], json!([{"name": "home", "fingerprint": "abcd", "seconds_remaining": 60}]));
+        let strings = [
+            "status", "operation", "input", "output", "validation_string", "expected_validation_string",
+            "error_code", "message", "version", "password", "socket", "name",
+        ];
+        for field in strings {
+            assert!(value[field].is_string(), "{field} is not a string");
+        }
+    }
+
+    #[test]
+    fn paths_are_escaped() {
+        let path = "a \"quoted\" C:\\dir\nline\ttab\u{1}bell";
+        let rendered = Report::ok("decrypt").paths(path, None).render(Format::Json);
+        assert!(!rendered.contains('\n'));
+        assert!(rendered.contains(r#""input":"a \"quoted\" C:\\dir\nline\ttab\u0001bell""#), "{rendered}");
+        assert_eq!(serde_json::from_str::<Value>(&rendered).unwrap()["input"], path);
+    }
+
+    #[test]
+    fn text_and_json() {
+        let mut report = Report::ok("encrypt").paths("plain", Some("plain.e"));
+        report.validation_string = Some("hash".into());
+        let json = report.render(Format::Json);
+        assert_eq!(json, r#"{"schema":1,"status":"ok","operation":"encrypt","input":"plain","output":"plain.e","validation_string":"hash"}"#);
+        assert_eq!(report.render(Format::Text), "status: ok\noperation: encrypt\ninput: plain\noutput: plain.e\nvalidation string: hash");
+
+        let mut report = Report::ok("agent");
+        report.entropy_bits = Some(52.0);
+        report.keys = Some(vec![AgentKey { name: "home".into(), fingerprint: "abcd".into(), seconds_remaining: 60 }]);
+        assert_eq!(report.render(Format::Text), "status: ok\noperation: agent\nentropy bits: 52.0\nkeys:\n  home  abcd  60");
+    }
+
+    #[test]
+    fn failures() {
+        let (report, exit_code) = failure(&CliError::Keyfile("insecure".into()), "decrypt", Some(("in.e", Some("out"))));
+        assert_eq!(exit_code, 9);
+        assert_eq!(parse(&report), json!({
+            "schema": 1, "status": "error", "operation": "decrypt", "input": "in.e", "output": "out", "error_code": "E_KEYFILE", "message": "insecure",
+        }));
+
+        let mismatch = Error::Mismatch { found: "found".into(), expected: "expected".into() };
+        let (report, exit_code) = failure(&mismatch, "decrypt", None);
+        assert_eq!((report.error_code, exit_code), (Some("E_AUTH"), 3));
+        assert_eq!((report.validation_string.as_deref(), report.expected_validation_string.as_deref()), (Some("found"), Some("expected")));
+
+        let (report, _) = failure(&std::io::Error::other("disk"), "encrypt", None);
+        assert_eq!(report.error_code, Some("E_IO"));
+        let (report, _) = failure(&std::fmt::Error, "encrypt", None);
+        assert_eq!(report.error_code, Some("E_FAILED"));
+    }
+}
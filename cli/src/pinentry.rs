@@ -0,0 +1,198 @@
+//! Password prompts through a pinentry program, speaking the Assuan protocol over its STDIN and STDOUT.
+//!
+//! Set ENCHANTER_PINENTRY to the pinentry program (for example /usr/bin/pinentry-gnome3) to use it
+//! instead of the terminal prompt. This allows enchanter to be used from GUI launchers, editors,
+//! and hooks that have no terminal.
+
+use zeroize::Zeroizing;
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+/// The longest line the Assuan protocol allows, including the newline.
+const LINE_MAX: usize = 1000;
+
+/// The text shown by the pinentry dialog.
+pub struct Prompt<'a> {
+    pub description: &'a str,
+    pub prompt: &'a str,
+    /// Ask for the password twice, and have pinentry check that both entries match.
+    pub confirm: bool,
+    /// An error from a previous attempt, such as a wrong password, shown above the entry.
+    pub error: Option<&'a str>,
+}
+
+/// The configured pinentry program, if any.
+pub fn program() -> Option<String> {
+    env::var("ENCHANTER_PINENTRY").ok().filter(|p| !p.is_empty())
+}
+
+/// Percent escape text for an Assuan command line.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '%' => out.push_str("%25"),
+            '\r' => out.push_str("%0D"),
+            '\n' => out.push_str("%0A"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Decode percent escapes in an Assuan data line, appending the bytes to out.
+fn unescape_into(data: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'%' && i + 2 < data.len() {
+            let hex = std::str::from_utf8(&data[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(data[i]);
+        i += 1;
+    }
+}
+
+/// Read one line into the buffer, without the line ending. The line is read a byte at a time, so
+/// no copy of a data line is left behind in a read buffer. Returns false at the end of the input.
+fn read_line<R: Read>(reader: &mut R, line: &mut Vec<u8>) -> io::Result<bool> {
+    line.clear();
+    let mut byte = [0u8; 1];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) => return Ok(!line.is_empty()),
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => line.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(true)
+}
+
+/// Read response lines until OK or ERR, collecting any data lines. The line buffer and the data
+/// are allocated up front for a whole Assuan line, so a PIN is not copied by a reallocation, and
+/// both are zeroized.
+fn response<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<Vec<u8>> {
+    let mut data = Zeroizing::new(Vec::with_capacity(LINE_MAX));
+    let mut line = Zeroizing::new(Vec::with_capacity(LINE_MAX));
+    loop {
+        if !read_line(reader, &mut line)? {
+            return Err(io::Error::other("pinentry closed the connection"));
+        }
+        if line.as_slice() == b"OK" || line.starts_with(b"OK ") {
+            return Ok(std::mem::take(&mut *data));
+        } else if let Some(err) = line.strip_prefix(b"ERR ") {
+            let err = String::from_utf8_lossy(err);
+            let message = err.split_once(' ').map(|(_, m)| m).unwrap_or(&err);
+            return Err(io::Error::other(format!("pinentry: {message}")));
+        } else if let Some(chunk) = line.strip_prefix(b"D ") {
+            unescape_into(chunk, &mut data);
+        } else if line.starts_with(b"INQUIRE") {
+            writer.write_all(b"END\n")?;
+            writer.flush()?;
+        }
+        // Status (S) and comment (#) lines are ignored.
+    }
+}
+
+/// Send a command and wait for its response.
+fn command<R: Read, W: Write>(reader: &mut R, writer: &mut W, line: &str) -> io::Result<Vec<u8>> {
+    writer.write_all(line.as_bytes())?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    response(reader, writer)
+}
+
+/// Ask the pinentry program for a password.
+pub fn get_pin(program: &str, prompt: &Prompt) -> io::Result<Vec<u8>> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::other(format!("Failed to start pinentry {program}: {e}")))?;
+    let mut writer = child.stdin.take().ok_or_else(|| io::Error::other("pinentry has no STDIN"))?;
+    let mut reader = child.stdout.take().ok_or_else(|| io::Error::other("pinentry has no STDOUT"))?;
+
+    let result = (|| {
+        // The greeting.
+        response(&mut reader, &mut writer)?;
+        if let Ok(tty) = env::var("GPG_TTY") {
+            command(&mut reader, &mut writer, &format!("OPTION ttyname={}", escape(&tty)))?;
+        }
+        command(&mut reader, &mut writer, "SETTITLE enchanter")?;
+        command(&mut reader, &mut writer, &format!("SETDESC {}", escape(prompt.description)))?;
+        command(&mut reader, &mut writer, &format!("SETPROMPT {}", escape(prompt.prompt)))?;
+        if prompt.confirm {
+            command(&mut reader, &mut writer, "SETREPEAT Repeat:")?;
+            command(&mut reader, &mut writer, "SETREPEATERROR Passwords do not match")?;
+        }
+        if let Some(error) = prompt.error {
+            command(&mut reader, &mut writer, &format!("SETERROR {}", escape(error)))?;
+        }
+        command(&mut reader, &mut writer, "GETPIN")
+    })();
+
+    let _ = writer.write_all(b"BYE\n");
+    drop(writer);
+    let _ = child.wait();
+    result
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A scripted pinentry that answers every command with OK and returns a fixed PIN,
+    /// logging the commands it received so the test can inspect them.
+    fn fake_pinentry(dir: &std::path::Path, pin: &str) -> String {
+        let script = dir.join("pinentry");
+        let log = dir.join("log");
+        fs::write(&script, format!(
+            "#!/bin/sh\necho 'OK Pleased to meet you'\nwhile read -r cmd; do\n  echo \"$cmd\" >> {}\n  case \"$cmd\" in\n    GETPIN) echo 'D {}'; echo OK ;;\n    BYE) echo OK; exit 0 ;;\n    *) echo OK ;;\n  esac\ndone\n",
+            log.display(), pin
+        )).unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o700)).unwrap();
+        script.display().to_string()
+    }
+
+    #[test]
+    fn pinentry_roundtrip() {
+      let dir = env::temp_dir().join(format!("enchanter-pinentry-{}", std::process::id()));
+      fs::create_dir_all(&dir).unwrap();
+      let program = fake_pinentry(&dir, "s3cret%25pass");
+      let prompt = Prompt { description: "Decrypt 100% of file", prompt: "Password:", confirm: true, error: Some("Wrong password") };
+      let pin = get_pin(&program, &prompt).unwrap();
+      assert_eq!(pin, b"s3cret%pass");
+      let log = fs::read_to_string(dir.join("log")).unwrap();
+      assert!(log.contains("SETDESC Decrypt 100%25 of file"));
+      assert!(log.contains("SETREPEAT"));
+      assert!(log.contains("SETERROR Wrong password"));
+      let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn data_lines_are_decoded() {
+      let mut reader = io::Cursor::new(b"S PASSWORD_FROM_CACHE\r\nD pa%0Ass\nINQUIRE QUALITY\nD %25word%2\nOK\n".to_vec());
+      let mut writer = Vec::new();
+      assert_eq!(response(&mut reader, &mut writer).unwrap(), b"pa\nss%word%2");
+      assert_eq!(writer, b"END\n");
+
+      let mut reader = io::Cursor::new(b"D secret\nERR 83886179 Operation cancelled\n".to_vec());
+      assert_eq!(response(&mut reader, &mut writer).unwrap_err().to_string(), "pinentry: Operation cancelled");
+      let mut reader = io::Cursor::new(b"D secret".to_vec());
+      assert_eq!(response(&mut reader, &mut writer).unwrap_err().to_string(), "pinentry closed the connection");
+    }
+}
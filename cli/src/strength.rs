@@ -0,0 +1,155 @@
+//! A small zxcvbn style password strength estimator.
+//!
+//! The password is split into the cheapest sequence of patterns an attacker would try:
+//! common passwords and words (with l33t substitutions undone), repeated characters,
+//! keyboard-free sequences like "abc" or "4321", years, and finally single brute forced characters.
+//! The guesses for each pattern are multiplied together, and the total is mapped to a 0 to 4 score
+//! with the same thresholds as zxcvbn.
+
+/// Common passwords and words, most common first. The position is used as the guess rank.
+const COMMON: &[&str] = &[
+    "password", "123456", "qwerty", "letmein", "welcome", "monkey", "dragon", "football",
+    "iloveyou", "admin", "login", "master", "sunshine", "princess", "shadow", "baseball",
+    "superman", "trustno1", "hello", "freedom", "whatever", "qazwsx", "michael", "charlie",
+    "jordan", "jennifer", "hunter", "buster", "soccer", "harley", "batman", "andrew",
+    "tigger", "starwars", "pepper", "ginger", "summer", "winter", "spring", "autumn",
+    "secret", "access", "passw0rd", "changeme", "default", "root", "toor", "test",
+    "guest", "love", "money", "family", "flower", "hockey", "killer", "george",
+    "computer", "internet", "cheese", "orange", "banana", "apple", "purple", "yellow",
+    "silver", "golden", "diamond", "forever", "mustang", "maverick", "thunder", "matrix",
+    "enchanter", "encrypt", "crypto", "secure", "security", "private", "backup", "server",
+    "office", "company", "business", "london", "paris", "berlin", "america", "canada",
+    "house", "garden", "water", "fire", "earth", "happy", "lucky", "magic",
+    "angel", "blue", "green", "black", "white", "red", "cat", "dog",
+];
+
+/// The result of estimating the strength of a password.
+pub struct Estimate {
+    /// Base 10 logarithm of the estimated number of guesses needed.
+    pub guesses_log10: f64,
+    /// 0 (too guessable) to 4 (very unguessable), using zxcvbn thresholds.
+    pub score: u8,
+    /// A short explanation of the weakest pattern found, if any.
+    pub warning: Option<&'static str>,
+}
+
+/// Undo common l33t substitutions so "p@ssw0rd" matches "password".
+fn unleet(c: char) -> char {
+    match c {
+        '@' | '4' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' => 't',
+        _ => c.to_ascii_lowercase(),
+    }
+}
+
+/// The size of the character class a brute force attack would need for this character.
+fn cardinality(c: char) -> f64 {
+    if c.is_ascii_lowercase() || c.is_ascii_uppercase() {
+        26.0
+    } else if c.is_ascii_digit() {
+        10.0
+    } else if c.is_ascii() {
+        33.0
+    } else {
+        100.0
+    }
+}
+
+/// Find the cheapest pattern starting at position i: (length, log10 guesses, warning).
+fn pattern_at(chars: &[char], i: usize) -> (usize, f64, Option<&'static str>) {
+    let rest = &chars[i..];
+    let mut best = (1, cardinality(rest[0]).log10(), None);
+
+    // Common passwords and words, matched case-insensitively with l33t substitutions undone.
+    let normalized: String = rest.iter().map(|&c| unleet(c)).collect();
+    let plain: String = rest.iter().map(|c| c.to_ascii_lowercase()).collect();
+    for (rank, word) in COMMON.iter().enumerate() {
+        let len = word.chars().count();
+        if len >= 3 && (normalized.starts_with(word) || plain.starts_with(word)) {
+            // Allow for capitalization and substitution variants of the word.
+            let guesses = ((rank + 1) as f64 * 4.0).log10();
+            if len > best.0 || guesses < best.1 {
+                best = (len, guesses, Some("Contains a common password or word"));
+            }
+        }
+    }
+
+    // Repeated characters, like "aaaa".
+    let repeat = rest.iter().take_while(|&&c| c == rest[0]).count();
+    if repeat >= 3 && repeat > best.0 {
+        best = (repeat, (cardinality(rest[0]) * repeat as f64).log10(), Some("Contains repeated characters"));
+    }
+
+    // Ascending or descending sequences, like "abcd" or "9876".
+    if rest.len() >= 3 {
+        let step = rest[1] as i64 - rest[0] as i64;
+        if step == 1 || step == -1 {
+            let run = 1 + rest.windows(2).take_while(|w| w[1] as i64 - w[0] as i64 == step).count();
+            if run >= 3 && run > best.0 {
+                best = (run, (cardinality(rest[0]) * run as f64 * 2.0).log10(), Some("Contains a sequence like abc or 123"));
+            }
+        }
+    }
+
+    // Years, which are among the first things tried.
+    if rest.len() >= 4 {
+        let year: String = rest[..4].iter().collect();
+        if let Ok(y) = year.parse::<u32>() && (1900..2100).contains(&y) && best.0 < 4 {
+            best = (4, 200f64.log10(), Some("Contains a year"));
+        }
+    }
+
+    best
+}
+
+/// Estimate how many guesses an attacker would need for the password.
+pub fn estimate(password: &str) -> Estimate {
+    let chars: Vec<char> = password.chars().collect();
+    if chars.is_empty() {
+        return Estimate { guesses_log10: 0.0, score: 0, warning: Some("The password is empty") };
+    }
+    let mut guesses_log10 = 0.0;
+    let mut warning = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let (len, log10, found) = pattern_at(&chars, i);
+        guesses_log10 += log10;
+        if warning.is_none() {
+            warning = found;
+        }
+        i += len;
+    }
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+    Estimate { guesses_log10, score, warning }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weak_passwords() {
+      assert_eq!(estimate("").score, 0);
+      assert_eq!(estimate("password").score, 0);
+      assert_eq!(estimate("P@ssw0rd").score, 0);
+      assert!(estimate("aaaaaaaaaaaa").score <= 1);
+      assert!(estimate("abcdef123456").score <= 1);
+    }
+
+    #[test]
+    fn strong_passwords() {
+      assert_eq!(estimate("correct horse battery staple").score, 4);
+      assert_eq!(estimate("RWw5XjBXQmhBLi43VGIwSCZfXl4x").score, 4);
+      assert!(estimate("tr0ub4dor&3").score >= 3);
+    }
+}
@@ -0,0 +1,114 @@
+/* Round trips through the C ABI. Run ffi/test.sh to build and run it. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "enchanter.h"
+
+#define CHECK(call, expected)                                                   \
+  do {                                                                          \
+    int32_t code = (call);                                                      \
+    if (code != (expected)) {                                                   \
+      fprintf(stderr, "%s:%d: %s returned %s, expected %s\n", __FILE__, __LINE__, \
+              #call, enchanter_error_name(code), enchanter_error_name(expected)); \
+      exit(1);                                                                  \
+    }                                                                           \
+  } while (0)
+
+static void write_file(const char *path, const char *contents) {
+  FILE *f = fopen(path, "wb");
+  if (f == NULL || fputs(contents, f) == EOF || fclose(f) != 0) {
+    fprintf(stderr, "failed to write %s\n", path);
+    exit(1);
+  }
+}
+
+static void check_file(const char *path, const char *contents) {
+  char buffer[256] = {0};
+  FILE *f = fopen(path, "rb");
+  if (f == NULL) {
+    fprintf(stderr, "failed to read %s\n", path);
+    exit(1);
+  }
+  size_t len = fread(buffer, 1, sizeof(buffer) - 1, f);
+  fclose(f);
+  if (len != strlen(contents) || memcmp(buffer, contents, len) != 0) {
+    fprintf(stderr, "%s does not match\n", path);
+    exit(1);
+  }
+}
+
+int main(int argc, char **argv) {
+  const char *dir = argc > 1 ? argv[1] : ".";
+  const char *password = "test-case12341234";
+  const char *message = "Hello from C";
+
+  EnchanterKey *key = NULL;
+  CHECK(enchanter_derive_key((const uint8_t *)password, strlen(password), &key), ENCHANTER_OK);
+
+  /* Buffers. */
+  EnchanterBuffer *ciphertext = NULL;
+  char *validation = NULL;
+  CHECK(enchanter_encrypt(key, (const uint8_t *)message, strlen(message), &ciphertext, &validation), ENCHANTER_OK);
+
+  const uint8_t *data = enchanter_buffer_data(ciphertext);
+  size_t len = enchanter_buffer_len(ciphertext);
+  char *computed = NULL;
+  CHECK(enchanter_validation_string(key, data, len, &computed), ENCHANTER_OK);
+  if (strcmp(validation, computed) != 0) {
+    fprintf(stderr, "validation strings differ\n");
+    return 1;
+  }
+
+  EnchanterBuffer *plaintext = NULL;
+  CHECK(enchanter_decrypt(key, data, len, validation, &plaintext), ENCHANTER_OK);
+  if (enchanter_buffer_len(plaintext) != strlen(message) ||
+      memcmp(enchanter_buffer_data(plaintext), message, strlen(message)) != 0) {
+    fprintf(stderr, "decrypted buffer does not match\n");
+    return 1;
+  }
+  enchanter_buffer_free(plaintext);
+
+  CHECK(enchanter_decrypt(key, data, len, "wrong", &plaintext), ENCHANTER_E_AUTH);
+  CHECK(enchanter_decrypt(key, data, len - 1, NULL, &plaintext), ENCHANTER_E_AUTH);
+  CHECK(enchanter_decrypt(NULL, data, len, NULL, &plaintext), ENCHANTER_E_USAGE);
+
+  uint8_t wrong_bytes[32] = {0};
+  EnchanterKey *wrong = NULL;
+  CHECK(enchanter_key_from_bytes(wrong_bytes, &wrong), ENCHANTER_OK);
+  CHECK(enchanter_decrypt(wrong, data, len, NULL, &plaintext), ENCHANTER_E_AUTH);
+  enchanter_key_free(wrong);
+
+  enchanter_buffer_free(ciphertext);
+  enchanter_string_free(validation);
+  enchanter_string_free(computed);
+
+  /* Files, in both formats. */
+  char input[512], encrypted[512], decrypted[512];
+  snprintf(input, sizeof(input), "%s/roundtrip.o", dir);
+  snprintf(encrypted, sizeof(encrypted), "%s/roundtrip.e", dir);
+  snprintf(decrypted, sizeof(decrypted), "%s/roundtrip.d", dir);
+  write_file(input, message);
+
+  uint32_t chunk_sizes[] = {0, 4, ENCHANTER_DEFAULT_CHUNK_SIZE};
+  for (size_t i = 0; i < sizeof(chunk_sizes) / sizeof(chunk_sizes[0]); i++) {
+    char *written = NULL;
+    char *read = NULL;
+    CHECK(enchanter_encrypt_file(key, input, encrypted, chunk_sizes[i], &written), ENCHANTER_OK);
+    CHECK(enchanter_decrypt_file(key, encrypted, decrypted, written, &read), ENCHANTER_OK);
+    check_file(decrypted, message);
+    if (strcmp(written, read) != 0) {
+      fprintf(stderr, "validation strings differ\n");
+      return 1;
+    }
+    enchanter_string_free(written);
+    enchanter_string_free(read);
+  }
+  CHECK(enchanter_decrypt_file(key, encrypted, decrypted, "wrong", NULL), ENCHANTER_E_AUTH);
+  CHECK(enchanter_decrypt_file(key, input, decrypted, NULL, NULL), ENCHANTER_E_TRUNCATED);
+
+  enchanter_key_free(key);
+  printf("ffi round trips passed\n");
+  return 0;
+}
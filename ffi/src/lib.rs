@@ -0,0 +1,4 @@
+//! The enchanter C ABI as shared and static libraries. Everything is in `enchanter::ffi`,
+//! declared in include/enchanter.h.
+
+pub use enchanter::ffi::*;
@@ -0,0 +1,11 @@
+#!/bin/sh
+# Build the static library from the enchanter-ffi package and run the C round trip test against it.
+set -eu
+
+cd "$(dirname "$0")/.."
+out="${TMPDIR:-/tmp}/enchanter-ffi"
+mkdir -p "$out"
+
+cargo build --release -p enchanter-ffi
+${CC:-cc} -Wall -Wextra -Werror -std=c99 -Iinclude ffi/roundtrip.c target/release/libenchanter_ffi.a -lpthread -ldl -lm -o "$out/roundtrip"
+"$out/roundtrip" "$out"
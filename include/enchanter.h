@@ -0,0 +1,223 @@
+#ifndef ENCHANTER_H
+#define ENCHANTER_H
+
+/* Generated with cbindgen:0.29.4 */
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define ENCHANTER_OK 0
+
+#define ENCHANTER_E_FAILED 1
+
+/**
+ * Also returned for a null pointer that is required, or a string that is not UTF-8.
+ */
+#define ENCHANTER_E_USAGE 2
+
+#define ENCHANTER_E_AUTH 3
+
+#define ENCHANTER_E_MANIFEST_MISSING 4
+
+#define ENCHANTER_E_MANIFEST_INVALID 5
+
+#define ENCHANTER_E_KDF 6
+
+#define ENCHANTER_E_IO 7
+
+#define ENCHANTER_E_TRUNCATED 8
+
+#define ENCHANTER_E_MALFORMED 11
+
+#define ENCHANTER_E_CANCELLED 12
+
+/**
+ * The library panicked. This is a bug.
+ */
+#define ENCHANTER_E_PANIC -1
+
+/**
+ * The chunk size the enchanter command uses for --chunked, 1 MiB.
+ */
+#define ENCHANTER_DEFAULT_CHUNK_SIZE (1 << 20)
+
+/**
+ * Bytes returned by the library, zeroized when freed.
+ */
+typedef struct EnchanterBuffer EnchanterBuffer;
+
+/**
+ * A 32 byte key, zeroized when freed.
+ */
+typedef struct EnchanterKey EnchanterKey;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Derive a key from a password and salt with the three Argon2id rounds of a3.
+ *
+ * # Safety
+ * password and salt must point to password_len and salt_len readable bytes, and out must be a
+ * valid pointer to write the key handle to.
+ */
+int32_t enchanter_a3(const uint8_t *password,
+                     size_t password_len,
+                     const uint8_t *salt,
+                     size_t salt_len,
+                     struct EnchanterKey **out);
+
+/**
+ * Derive a key from a password the way the enchanter command does, so files can be shared with it.
+ *
+ * # Safety
+ * password must point to password_len readable bytes, and out must be a valid pointer to write
+ * the key handle to.
+ */
+int32_t enchanter_derive_key(const uint8_t *password,
+                             size_t password_len,
+                             struct EnchanterKey **out);
+
+/**
+ * Make a key handle from 32 bytes of key material.
+ *
+ * # Safety
+ * bytes must point to 32 readable bytes, and out must be a valid pointer to write the key handle to.
+ */
+int32_t enchanter_key_from_bytes(const uint8_t *bytes,
+                                 struct EnchanterKey **out);
+
+/**
+ * Zeroize and free a key handle. A null pointer is ignored.
+ *
+ * # Safety
+ * key must be null or a handle from this library that has not been freed yet.
+ */
+void enchanter_key_free(struct EnchanterKey *key);
+
+/**
+ * Encrypt a buffer into the single buffer format, the same bytes as an encrypted file.
+ * The validation string is written to validation_string unless it is null.
+ *
+ * # Safety
+ * key must be a valid key handle, plaintext must point to plaintext_len readable bytes, out must be
+ * a valid pointer, and validation_string must be null or a valid pointer.
+ */
+int32_t enchanter_encrypt(const struct EnchanterKey *key,
+                          const uint8_t *plaintext,
+                          size_t plaintext_len,
+                          struct EnchanterBuffer **out,
+                          char **validation_string);
+
+/**
+ * Decrypt a buffer in either format. With a non-null expected validation string, a mismatch is
+ * ENCHANTER_E_AUTH and nothing is decrypted.
+ *
+ * # Safety
+ * key must be a valid key handle, ciphertext must point to ciphertext_len readable bytes, expected
+ * must be null or a NUL terminated string, and out must be a valid pointer.
+ */
+int32_t enchanter_decrypt(const struct EnchanterKey *key,
+                          const uint8_t *ciphertext,
+                          size_t ciphertext_len,
+                          const char *expected,
+                          struct EnchanterBuffer **out);
+
+/**
+ * Encrypt a file. A chunk_size of 0 writes the single buffer format, anything else the chunked
+ * format with chunks of that size. The validation string is written to validation_string unless
+ * it is null.
+ *
+ * # Safety
+ * key must be a valid key handle, input and output must be NUL terminated paths, and
+ * validation_string must be null or a valid pointer.
+ */
+int32_t enchanter_encrypt_file(const struct EnchanterKey *key,
+                               const char *input,
+                               const char *output,
+                               uint32_t chunk_size,
+                               char **validation_string);
+
+/**
+ * Decrypt a file in either format. With a non-null expected validation string, a mismatch is
+ * ENCHANTER_E_AUTH and the output file is not created. The validation string is written to
+ * validation_string unless it is null.
+ *
+ * # Safety
+ * key must be a valid key handle, input and output must be NUL terminated paths, expected must be
+ * null or a NUL terminated string, and validation_string must be null or a valid pointer.
+ */
+int32_t enchanter_decrypt_file(const struct EnchanterKey *key,
+                               const char *input,
+                               const char *output,
+                               const char *expected,
+                               char **validation_string);
+
+/**
+ * Compute the validation string (the enchanter.toml ciphertext_hash) of a ciphertext for a key.
+ *
+ * # Safety
+ * key must be a valid key handle, ciphertext must point to ciphertext_len readable bytes, and out
+ * must be a valid pointer.
+ */
+int32_t enchanter_validation_string(const struct EnchanterKey *key,
+                                    const uint8_t *ciphertext,
+                                    size_t ciphertext_len,
+                                    char **out);
+
+/**
+ * The bytes of a buffer, valid until it is freed.
+ *
+ * # Safety
+ * buffer must be a buffer handle from this library that has not been freed yet.
+ */
+const uint8_t *enchanter_buffer_data(const struct EnchanterBuffer *buffer);
+
+/**
+ * The length of a buffer.
+ *
+ * # Safety
+ * buffer must be a buffer handle from this library that has not been freed yet.
+ */
+size_t enchanter_buffer_len(const struct EnchanterBuffer *buffer);
+
+/**
+ * Zeroize and free a buffer handle. A null pointer is ignored.
+ *
+ * # Safety
+ * buffer must be null or a buffer handle from this library that has not been freed yet.
+ */
+void enchanter_buffer_free(struct EnchanterBuffer *buffer);
+
+/**
+ * Free a string returned by the library. A null pointer is ignored.
+ *
+ * # Safety
+ * s must be null or a string from this library that has not been freed yet.
+ */
+void enchanter_string_free(char *s);
+
+/**
+ * Zeroize memory owned by the caller, such as a password, in a way the compiler won't remove.
+ *
+ * # Safety
+ * data must point to len writable bytes, or be null.
+ */
+void enchanter_zeroize(uint8_t *data, size_t len);
+
+/**
+ * The stable name of an error code, such as "E_AUTH", as a static string.
+ */
+const char *enchanter_error_name(int32_t code);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ENCHANTER_H */
@@ -0,0 +1,20 @@
+#!/bin/sh
+# Build the enchanter Python module into a virtualenv and run python/tests against it and the
+# enchanter command. Uses maturin when it is installed, and cargo alone otherwise.
+set -eu
+
+cd "$(dirname "$0")/.."
+out="${TMPDIR:-/tmp}/enchanter-python"
+python3 -m venv "$out/venv"
+python="$out/venv/bin/python"
+
+if command -v maturin >/dev/null; then
+  VIRTUAL_ENV="$out/venv" maturin develop --release
+else
+  PYO3_BUILD_EXTENSION_MODULE=1 cargo rustc --lib --release --no-default-features --features python --crate-type cdylib
+  site="$("$python" -c 'import sysconfig; print(sysconfig.get_paths()["platlib"])')"
+  cp target/release/libenchanter.so "$site/enchanter.abi3.so"
+fi
+
+cargo build --release -p enchanter-cli
+ENCHANTER_BIN="$PWD/target/release/enchanter" "$python" -m unittest discover -s python/tests -v
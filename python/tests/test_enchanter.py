@@ -0,0 +1,122 @@
+"""Tests for the enchanter Python module, against files made by the enchanter command.
+
+Run python/test.sh, which builds the module into a virtualenv and sets ENCHANTER_BIN.
+"""
+
+import os
+import subprocess
+import tempfile
+import threading
+import unittest
+
+import enchanter
+
+PASSWORD = "test-case12341234"
+PLAINTEXT = b"rows,columns\n" * 1000
+
+
+class EnchanterTest(unittest.TestCase):
+    @classmethod
+    def setUpClass(cls):
+        cls.key = enchanter.derive_key(PASSWORD.encode())
+
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        self.addCleanup(self.dir.cleanup)
+
+    def path(self, name):
+        return os.path.join(self.dir.name, name)
+
+    def enchanter(self, *args):
+        """Run the enchanter command in the test directory, with the password in ENC."""
+        env = dict(os.environ, ENC=PASSWORD)
+        subprocess.run([os.environ["ENCHANTER_BIN"], *args], cwd=self.dir.name, env=env, check=True, capture_output=True)
+
+    def write(self, name, data):
+        with open(self.path(name), "wb") as f:
+            f.write(data)
+
+    def read(self, name):
+        with open(self.path(name), "rb") as f:
+            return f.read()
+
+    def test_bytes_round_trip(self):
+        ciphertext, validation = enchanter.encrypt(self.key, PLAINTEXT)
+        self.assertEqual(enchanter.validation_string(self.key, ciphertext), validation)
+        self.assertTrue(enchanter.verify(self.key, ciphertext, validation))
+        self.assertEqual(enchanter.decrypt(self.key, ciphertext, validation), PLAINTEXT)
+        self.assertEqual(enchanter.decrypt(self.key, ciphertext), PLAINTEXT)
+
+    def test_errors(self):
+        ciphertext, validation = enchanter.encrypt(self.key, b"secret")
+        self.assertFalse(enchanter.verify(self.key, ciphertext, "wrong"))
+        with self.assertRaises(enchanter.EnchanterError) as raised:
+            enchanter.decrypt(self.key, ciphertext, "wrong")
+        self.assertEqual(raised.exception.args[0], "E_AUTH")
+        with self.assertRaises(enchanter.EnchanterError) as raised:
+            enchanter.decrypt(enchanter.Key(bytes(32)), ciphertext)
+        self.assertEqual(raised.exception.args[0], "E_AUTH")
+        with self.assertRaises(enchanter.EnchanterError) as raised:
+            enchanter.decrypt(self.key, ciphertext[:20])
+        self.assertEqual(raised.exception.args[0], "E_TRUNCATED")
+        with self.assertRaises(enchanter.EnchanterError) as raised:
+            enchanter.Key(b"short")
+        self.assertEqual(raised.exception.args[0], "E_KDF")
+        with self.assertRaises(enchanter.EnchanterError) as raised:
+            enchanter.read_manifest(self.path("enchanter.toml"))
+        self.assertEqual(raised.exception.args[0], "E_MANIFEST_MISSING")
+        self.assertEqual(repr(self.key), "Key(..)")
+
+    def test_a3(self):
+        self.assertEqual(repr(enchanter.a3(PASSWORD.encode(), b"sixteen byte salt")), "Key(..)")
+        with self.assertRaises(enchanter.EnchanterError):
+            enchanter.a3(PASSWORD.encode(), b"short")
+
+    def test_decrypt_command_files(self):
+        for flags in ([], ["--chunked", "--chunk-size", "4096"]):
+            with self.subTest(flags=flags):
+                self.write("data.csv", PLAINTEXT)
+                self.enchanter("data.csv", "data.csv.e", "-ee", *flags)
+                manifest = enchanter.read_manifest(self.path("enchanter.toml"))
+                self.assertEqual(manifest.ciphertext_path, "data.csv.e")
+                self.assertIsNotNone(manifest.creation_time)
+
+                ciphertext = self.read("data.csv.e")
+                self.assertTrue(enchanter.verify(self.key, ciphertext, manifest.ciphertext_hash))
+                self.assertEqual(enchanter.decrypt(self.key, ciphertext, manifest.ciphertext_hash), PLAINTEXT)
+                validation = enchanter.decrypt_file(self.key, self.path("data.csv.e"), self.path("out.csv"), manifest.ciphertext_hash)
+                self.assertEqual(validation, manifest.ciphertext_hash)
+                self.assertEqual(self.read("out.csv"), PLAINTEXT)
+
+    def test_command_decrypts_module_files(self):
+        for chunk_size in (0, 4096):
+            with self.subTest(chunk_size=chunk_size):
+                self.write("data.csv", PLAINTEXT)
+                validation = enchanter.encrypt_file(self.key, self.path("data.csv"), self.path("data.csv.e"), chunk_size)
+                with open(self.path("enchanter.toml"), "w") as f:
+                    f.write(f'ciphertext_hash = "{validation}"\n')
+                self.enchanter("data.csv.e", "out.csv", "-de")
+                self.assertEqual(self.read("out.csv"), PLAINTEXT)
+
+    def test_aes_256_gcm_siv(self):
+        self.write("data.csv", PLAINTEXT)
+        validation = enchanter.encrypt_file(self.key, self.path("data.csv"), self.path("data.csv.e"), 4096, cipher="aes-256-gcm-siv")
+        self.assertEqual(self.read("data.csv.e")[8], 1)
+        self.assertEqual(enchanter.decrypt(self.key, self.read("data.csv.e"), validation), PLAINTEXT)
+        with self.assertRaises(enchanter.EnchanterError) as raised:
+            enchanter.encrypt_file(self.key, self.path("data.csv"), self.path("data.csv.e"), cipher="aes-256-gcm-siv")
+        self.assertEqual(raised.exception.args[0], "E_USAGE")
+
+    def test_threads(self):
+        ciphertext, validation = enchanter.encrypt(self.key, PLAINTEXT)
+        results = []
+        threads = [threading.Thread(target=lambda: results.append(enchanter.decrypt(self.key, ciphertext, validation))) for _ in range(4)]
+        for thread in threads:
+            thread.start()
+        for thread in threads:
+            thread.join()
+        self.assertEqual(results, [PLAINTEXT] * 4)
+
+
+if __name__ == "__main__":
+    unittest.main()
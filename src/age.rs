@@ -0,0 +1,134 @@
+//! Converting between enchanter files and age v1 files, behind the `age` feature.
+//!
+//! `export` decrypts an enchanter ciphertext in memory and encrypts the plaintext to an age file,
+//! either for a passphrase (an scrypt recipient) or for X25519 recipients (`age1...` public keys).
+//! `import` goes the other way, from an age file, binary or ASCII armored, to the enchanter format.
+//! The plaintext only ever exists in memory, and is zeroized once it has been re-encrypted.
+
+use ::age::armor::{ArmoredReader, ArmoredWriter, Format as ArmorFormat};
+use ::age::secrecy::SecretString;
+use ::age::{scrypt, x25519, DecryptError, Decryptor, EncryptError, Encryptor};
+use zeroize::Zeroize;
+
+use std::io::{Read, Write};
+
+use crate::crypt_xchacha::{self, CipherSuite, Encrypted, Format};
+use crate::error::Error;
+
+/// The first line of a binary age v1 file.
+const MAGIC: &[u8] = b"age-encryption.org/v1\n";
+/// The first line of an ASCII armored age file.
+const ARMOR_BEGIN: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// Who an exported age file is encrypted to.
+pub enum Recipients {
+    /// An scrypt recipient, so the file is opened with the passphrase, as with `age -p`.
+    Passphrase(String),
+    /// X25519 public keys, such as `age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p`.
+    X25519(Vec<String>),
+}
+
+/// What an age file is opened with.
+pub enum Identities {
+    /// The passphrase of an scrypt recipient.
+    Passphrase(String),
+    /// X25519 secret keys, the `AGE-SECRET-KEY-1...` lines of an age identity file.
+    X25519(Vec<String>),
+}
+
+impl Drop for Recipients {
+    fn drop(&mut self) {
+        if let Recipients::Passphrase(passphrase) = self {
+          passphrase.zeroize();
+        }
+    }
+}
+
+impl Drop for Identities {
+    fn drop(&mut self) {
+        match self {
+          Identities::Passphrase(passphrase) => passphrase.zeroize(),
+          Identities::X25519(keys) => keys.zeroize(),
+        }
+    }
+}
+
+/// Whether data looks like an age file, binary or ASCII armored. Armor may follow whitespace.
+pub fn is_age(data: &[u8]) -> bool {
+    data.starts_with(MAGIC) || data.trim_ascii_start().starts_with(ARMOR_BEGIN)
+}
+
+/// Decrypt an enchanter ciphertext in either format and encrypt its plaintext to an age v1 file.
+/// With an expected validation string, a mismatch is an E_AUTH error and nothing is decrypted.
+/// With armor set, the age file is ASCII armored.
+pub fn export(data: &[u8], key: &[u8], expected: Option<&str>, recipients: &Recipients, armor: bool) -> Result<Vec<u8>, Error> {
+    let decrypted = crypt_xchacha::decrypt_validated(data, key, expected, 0)?;
+    let encryptor = match recipients {
+      Recipients::Passphrase(passphrase) => Encryptor::with_user_passphrase(SecretString::from(passphrase.clone())),
+      Recipients::X25519(keys) => {
+        let keys = keys.iter().map(|key| key.parse::<x25519::Recipient>()
+          .map_err(|e| Error::Usage(format!("{key} is not an age X25519 recipient: {e}"))))
+          .collect::<Result<Vec<_>, Error>>()?;
+        Encryptor::with_recipients(keys.iter().map(|key| key as _)).map_err(encrypt_error)?
+      },
+    };
+    let mut output = Vec::with_capacity(decrypted.plaintext.len() + 256);
+    let format = if armor { ArmorFormat::AsciiArmor } else { ArmorFormat::Binary };
+    let armored = ArmoredWriter::wrap_output(&mut output, format).map_err(io_error)?;
+    let mut writer = encryptor.wrap_output(armored).map_err(io_error)?;
+    writer.write_all(&decrypted.plaintext).map_err(io_error)?;
+    writer.finish().and_then(|armored| armored.finish()).map_err(io_error)?;
+    Ok(output)
+}
+
+/// Decrypt an age v1 file, binary or ASCII armored. A wrong passphrase or identity is an
+/// E_AUTH error, and a file that is not a valid age file is E_MALFORMED.
+pub fn decrypt(data: &[u8], identities: &Identities) -> Result<Vec<u8>, Error> {
+    let decryptor = Decryptor::new_buffered(ArmoredReader::new(data)).map_err(decrypt_error)?;
+    let mut reader = match identities {
+      Identities::Passphrase(passphrase) => {
+        let identity = scrypt::Identity::new(SecretString::from(passphrase.clone()));
+        decryptor.decrypt(std::iter::once(&identity as _)).map_err(decrypt_error)?
+      },
+      Identities::X25519(keys) => {
+        let keys = keys.iter().map(|key| key.parse::<x25519::Identity>()
+          .map_err(|e| Error::Usage(format!("Not an age X25519 identity: {e}"))))
+          .collect::<Result<Vec<_>, Error>>()?;
+        decryptor.decrypt(keys.iter().map(|key| key as _)).map_err(decrypt_error)?
+      },
+    };
+    let mut plaintext = Vec::with_capacity(data.len());
+    if let Err(e) = reader.read_to_end(&mut plaintext) {
+      plaintext.zeroize();
+      return Err(Error::Auth(format!("The age payload failed to authenticate: {e}")));
+    }
+    Ok(plaintext)
+}
+
+/// Convert an age v1 file to the enchanter format: decrypt it with the identities and encrypt
+/// the plaintext with the enchanter key.
+pub fn import(data: &[u8], identities: &Identities, key: &[u8], format: Format, suite: CipherSuite) -> Result<Encrypted, Error> {
+    let mut plaintext = decrypt(data, identities)?;
+    let ciphertext = crypt_xchacha::seal_format_suite(&plaintext, key, format, suite);
+    plaintext.zeroize();
+    let ciphertext = ciphertext?;
+    let validation_string = crypt_xchacha::validation_string(key, &ciphertext);
+    Ok(Encrypted { ciphertext, validation_string })
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::Io(format!("Failed to write the age file: {e}"))
+}
+
+fn encrypt_error(e: EncryptError) -> Error {
+    Error::Usage(format!("Failed to encrypt to the age recipients: {e}"))
+}
+
+fn decrypt_error(e: DecryptError) -> Error {
+    match e {
+      DecryptError::DecryptionFailed | DecryptError::KeyDecryptionFailed | DecryptError::NoMatchingKeys | DecryptError::InvalidMac =>
+        Error::Auth(format!("The age file can't be opened with the given passphrase or identity: {e}")),
+      DecryptError::ExcessiveWork { .. } => Error::Kdf(format!("The age file needs too much scrypt work: {e}")),
+      _ => Error::Malformed(format!("Not a valid age v1 file: {e}")),
+    }
+}
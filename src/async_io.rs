@@ -0,0 +1,240 @@
+//! Async encryption streams and file helpers for tokio, behind the `tokio` feature.
+//!
+//! `EncryptWriter` and `DecryptReader` are the `AsyncWrite` and `AsyncRead` counterparts of the
+//! `std::io` adapters, sharing their format handling, so the output is the same. The file helpers
+//! stream through them and can be cancelled with a `CancellationToken`, which is checked between
+//! reads. `a3` runs the Argon2 derivation on the blocking pool instead of stalling the runtime.
+
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio_util::sync::CancellationToken;
+use zeroize::Zeroize;
+
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use crate::crypt_xchacha::{self, CipherSuite, Format, Key};
+use crate::error::Error;
+use crate::range::unwrap_error;
+use crate::stream::{Decryptor, Encryptor, READ_SIZE};
+
+/// Encrypts everything written to it into the inner writer.
+///
+/// `finish` or `shutdown` must be called to write the last chunk and its tag. A stream that is
+/// dropped without either is incomplete and fails to decrypt.
+pub struct EncryptWriter<W> {
+    inner: W,
+    encryptor: Encryptor,
+    /// Ciphertext that has not been written to the inner writer yet, from written onwards.
+    out: Vec<u8>,
+    written: usize,
+    validation_string: Option<String>,
+}
+
+impl<W: AsyncWrite + Unpin> EncryptWriter<W> {
+    /// Start encrypting into the writer in the given format. The chunked header is written with
+    /// the first ciphertext, and the number of threads of a chunked format is not used.
+    pub fn new(inner: W, key: &[u8], format: Format) -> Result<EncryptWriter<W>, Error> {
+        EncryptWriter::with_suite(inner, key, format, CipherSuite::XChaCha20Poly1305)
+    }
+
+    /// The same as new, in the given cipher suite, which needs the chunked format unless it is
+    /// XChaCha20Poly1305.
+    pub fn with_suite(inner: W, key: &[u8], format: Format, suite: CipherSuite) -> Result<EncryptWriter<W>, Error> {
+        let mut out = Vec::new();
+        let encryptor = Encryptor::new(key, format, suite, &mut out)?;
+        Ok(EncryptWriter { inner, encryptor, out, written: 0, validation_string: None })
+    }
+
+    /// The validation string of everything that was written, once the stream is finished.
+    pub fn validation_string(&self) -> Option<&str> {
+        self.validation_string.as_deref()
+    }
+
+    fn poll_write_out(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.out.len() {
+          let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.out[self.written..]))?;
+          if n == 0 {
+            return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+          }
+          self.written += n;
+        }
+        self.out.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.validation_string.is_none() {
+          ready!(self.poll_write_out(cx))?;
+          self.validation_string = Some(self.encryptor.finish(&mut self.out).map_err(io::Error::other)?);
+        }
+        ready!(self.poll_write_out(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    /// Encrypt the rest of the plaintext, flush the inner writer, and return it with the
+    /// validation string of everything that was written.
+    pub async fn finish(mut self) -> Result<(W, String), Error> {
+        poll_fn(|cx| self.poll_finish(cx)).await.map_err(unwrap_error)?;
+        let validation_string = self.validation_string.take().unwrap_or_default();
+        Ok((self.inner, validation_string))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for EncryptWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_out(cx))?;
+        Poll::Ready(this.encryptor.push(buf, &mut this.out).map_err(io::Error::other))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_out(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_finish(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Decrypts the enchanter format from the inner reader.
+///
+/// Chunked streams are read one chunk at a time, and only authenticated plaintext is returned.
+/// Single buffer streams are read and authenticated in full on the first read.
+/// Errors are `io::Error`s wrapping an enchanter `Error`.
+pub struct DecryptReader<R> {
+    inner: R,
+    decryptor: Decryptor,
+    eof: bool,
+    /// Decrypted plaintext that has not been read yet, from position onwards.
+    plaintext: Vec<u8>,
+    position: usize,
+    scratch: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> DecryptReader<R> {
+    /// Start decrypting the reader. The format is detected from the first bytes.
+    pub fn new(inner: R, key: &[u8]) -> Result<DecryptReader<R>, Error> {
+        Ok(DecryptReader { inner, decryptor: Decryptor::new(key)?, eof: false, plaintext: Vec::new(), position: 0, scratch: Vec::new() })
+    }
+
+    /// The validation string of the whole ciphertext, once everything has been read.
+    pub fn validation_string(&self) -> Option<&str> {
+        self.decryptor.validation_string()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DecryptReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.position == this.plaintext.len() && !this.decryptor.done() {
+          if let Some(plaintext) = this.decryptor.next(this.eof).map_err(io::Error::other)? {
+            this.plaintext.zeroize();
+            this.plaintext = plaintext;
+            this.position = 0;
+            continue;
+          }
+          this.scratch.resize(READ_SIZE, 0);
+          let mut scratch = ReadBuf::new(&mut this.scratch);
+          ready!(Pin::new(&mut this.inner).poll_read(cx, &mut scratch))
+            .map_err(|e| io::Error::other(Error::Io(format!("Failed to read the ciphertext: {e}"))))?;
+          match scratch.filled().len() {
+            0 => this.eof = true,
+            n => this.decryptor.push(&this.scratch[..n]),
+          }
+        }
+        let n = (this.plaintext.len() - this.position).min(buf.remaining());
+        buf.put_slice(&this.plaintext[this.position..this.position + n]);
+        this.position += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R> Drop for DecryptReader<R> {
+    fn drop(&mut self) {
+        self.plaintext.zeroize();
+    }
+}
+
+/// Copy a stream until its end or until it is cancelled, yielding to the runtime between reads.
+async fn copy<R, W>(reader: &mut R, writer: &mut W, cancel: &CancellationToken) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = vec![0u8; READ_SIZE];
+    let result = loop {
+      let read = match cancel.run_until_cancelled(reader.read(&mut buffer)).await {
+        None => break Err(Error::Cancelled("The transfer was cancelled".into())),
+        Some(read) => read,
+      };
+      match read {
+        Ok(0) => break Ok(()),
+        Ok(n) => if let Err(e) = writer.write_all(&buffer[..n]).await {
+          break Err(unwrap_error(e));
+        },
+        Err(e) => break Err(unwrap_error(e)),
+      }
+      tokio::task::consume_budget().await;
+    };
+    buffer.zeroize();
+    result
+}
+
+/// Encrypt a file in the given format without blocking the runtime, and return the validation
+/// string of the output file. The output file is removed when encryption fails or is cancelled.
+pub async fn encrypt_file(input_file: &str, output_file: &str, key: &[u8], format: Format, cancel: &CancellationToken) -> Result<String, Error> {
+    let mut input = File::open(input_file).await.map_err(|e| Error::Io(format!("Failed to open the input file {input_file}: {e}")))?;
+    let output = File::create(output_file).await.map_err(|e| Error::Io(format!("Failed to create {output_file}: {e}")))?;
+    let result = async {
+      let mut writer = EncryptWriter::new(output, key, format)?;
+      copy(&mut input, &mut writer, cancel).await?;
+      let (_, validation_string) = writer.finish().await?;
+      Ok(validation_string)
+    }.await;
+    if result.is_err() {
+      let _ = tokio::fs::remove_file(output_file).await;
+    }
+    result
+}
+
+/// Decrypt a file in either format without blocking the runtime, and return its validation
+/// string. Authenticated chunks are written as they are decrypted, so the output file is removed
+/// when decryption fails, is cancelled, or the validation string is not the expected one.
+pub async fn decrypt_file(input_file: &str, output_file: &str, key: &[u8], expected: Option<&str>, cancel: &CancellationToken) -> Result<String, Error> {
+    let input = File::open(input_file).await.map_err(|e| Error::Io(format!("Failed to open the input file {input_file}: {e}")))?;
+    let mut output = File::create(output_file).await.map_err(|e| Error::Io(format!("Failed to create {output_file}: {e}")))?;
+    let result = async {
+      let mut reader = DecryptReader::new(input, key)?;
+      copy(&mut reader, &mut output, cancel).await?;
+      output.flush().await.map_err(|e| Error::Io(format!("Failed to write {output_file}: {e}")))?;
+      let validation_string = reader.validation_string().unwrap_or_default().to_string();
+      if let Some(expected) = expected {
+        crypt_xchacha::validate(&validation_string, expected)?;
+      }
+      Ok(validation_string)
+    }.await;
+    if result.is_err() {
+      let _ = tokio::fs::remove_file(output_file).await;
+    }
+    result
+}
+
+/// Derive a key with a3 on the blocking pool, so the Argon2 rounds do not stall the runtime.
+pub async fn a3(password: &[u8], salt: &[u8]) -> Result<Key, Error> {
+    let mut password = password.to_vec();
+    let salt = salt.to_vec();
+    let derivation = tokio::task::spawn_blocking(move || {
+      let key = Key::from_password(&password, &salt);
+      password.zeroize();
+      key
+    });
+    derivation.await.map_err(|e| Error::Kdf(format!("Argon2 key derivation did not finish: {e}")))?
+}
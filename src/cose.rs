@@ -0,0 +1,169 @@
+//! COSE_Encrypt0 messages (RFC 9052) with XChaCha20Poly1305, behind the `cose` feature.
+//!
+//! A message is the tagged CBOR array `16([protected, unprotected, ciphertext])`:
+//!
+//! ```text
+//! protected    {1: -65537, -65538: h'<a3 salt>', -65539: [19456, 2, 1]}
+//! unprotected  {5: h'<24 byte nonce>'}
+//! ciphertext   the ciphertext followed by the 16 byte tag
+//! ```
+//!
+//! XChaCha20Poly1305 has no IANA COSE algorithm, so it uses the private use algorithm -65537.
+//! The optional KDF headers are an enchanter-only label for how the key comes from a password:
+//! a3 with the salt, and the Argon2id memory cost in KiB, iterations and parallelism shared by its
+//! three rounds. The second and third rounds use salts fixed inside enchanter, so the headers are
+//! not enough for a general COSE library to derive the key. Only enchanter reads them, and devices
+//! are given the 32 byte key itself. The associated data is the Enc_structure of RFC 9052
+//! section 5.3 with an empty external AAD, so the protected header is authenticated.
+//!
+//! Converting from and to the enchanter format keeps the plaintext and the key. The bytes change,
+//! since the tag covers the COSE headers, and each conversion uses a fresh nonce rather than
+//! reusing the Poly1305 key of the original.
+
+use argon2::Params;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+#[allow(deprecated)]
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::XChaCha20Poly1305;
+use coset::cbor::value::Value;
+use coset::{Algorithm, CborSerializable, CoseEncrypt0, CoseEncrypt0Builder, CoseError, HeaderBuilder, Label, TaggedCborSerializable};
+use rand::rngs::OsRng;
+use rand_core::TryCryptoRng;
+use zeroize::Zeroize;
+
+use crate::crypt_xchacha::{self, CipherSuite, Encrypted, Format, TAG_LEN};
+use crate::error::Error;
+
+/// The private use COSE algorithm of XChaCha20Poly1305.
+pub const XCHACHA20POLY1305: i64 = -65537;
+/// The private use header holding the salt of the first a3 round, read only by enchanter.
+pub const KDF_SALT: i64 = -65538;
+/// The private use header holding the Argon2id memory cost, iterations and parallelism of the a3
+/// rounds. It labels the enchanter KDF rather than describing it fully.
+pub const KDF_PARAMS: i64 = -65539;
+
+/// The CBOR tag of COSE_Encrypt0 followed by the start of a three element array.
+const TAGGED_ENCRYPT0: &[u8] = &[0xd0, 0x83];
+
+/// How the key of a message is derived from a password: a3 with this salt, such as TUR or a keyfile's salt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Kdf {
+    pub salt: Vec<u8>,
+}
+
+impl Kdf {
+    /// Derive the key from a password with a3. An Argon2 failure is an E_KDF error.
+    pub fn derive_key(&self, password: &[u8]) -> Result<[u8; 32], Error> {
+        crypt_xchacha::try_a3(password, &self.salt)
+    }
+}
+
+/// Whether data looks like a tagged COSE_Encrypt0 message.
+pub fn is_cose(data: &[u8]) -> bool {
+    data.starts_with(TAGGED_ENCRYPT0)
+}
+
+/// Encrypt plaintext into a tagged COSE_Encrypt0 message, with a nonce from the OS RNG. With a
+/// KDF, its salt and the a3 Argon2id parameters go in the protected header.
+pub fn encrypt(plaintext: &[u8], key: &[u8], kdf: Option<&Kdf>) -> Result<Vec<u8>, Error> {
+    encrypt_with_rng(plaintext, key, kdf, &mut OsRng)
+}
+
+/// The same as encrypt, with the nonce from the caller's RNG.
+#[allow(deprecated)]
+pub fn encrypt_with_rng<R: TryCryptoRng + ?Sized>(plaintext: &[u8], key: &[u8], kdf: Option<&Kdf>, rng: &mut R) -> Result<Vec<u8>, Error> {
+    crypt_xchacha::check_key(key)?;
+    let mut nonce = [0u8; 24];
+    rng.try_fill_bytes(&mut nonce).map_err(|e| Error::Failed(format!("Failed to generate a nonce: {e}")))?;
+    let mut protected = HeaderBuilder::new().algorithm_label(Algorithm::PrivateUse(XCHACHA20POLY1305));
+    if let Some(kdf) = kdf {
+      let params = [Params::DEFAULT_M_COST, Params::DEFAULT_T_COST, Params::DEFAULT_P_COST];
+      protected = protected
+        .value(KDF_SALT, Value::Bytes(kdf.salt.clone()))
+        .value(KDF_PARAMS, Value::Array(params.into_iter().map(Value::from).collect()));
+    }
+    let aead = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+    let message = CoseEncrypt0Builder::new()
+      .protected(protected.build())
+      .unprotected(HeaderBuilder::new().iv(nonce.to_vec()).build())
+      .try_create_ciphertext(plaintext, &[], |plaintext, aad| {
+        aead.encrypt(GenericArray::from_slice(&nonce), Payload { msg: plaintext, aad })
+          .map_err(|_| Error::Failed("Failed to encrypt the plaintext".into()))
+      })?
+      .build();
+    message.to_tagged_vec().map_err(cose_error)
+}
+
+/// The KDF in the protected header of a message, if it has one. KDF parameters other than those
+/// of a3 are an E_KDF error.
+pub fn kdf(data: &[u8]) -> Result<Option<Kdf>, Error> {
+    let message = parse(data)?;
+    let header = |label: i64| message.protected.header.rest.iter().find(|(l, _)| *l == Label::Int(label)).map(|(_, value)| value);
+    let salt = match header(KDF_SALT) {
+      None => return Ok(None),
+      Some(Value::Bytes(salt)) => salt.clone(),
+      Some(_) => return Err(Error::Malformed("The COSE KDF salt is not a byte string".into())),
+    };
+    let params: Option<Vec<u32>> = match header(KDF_PARAMS) {
+      Some(Value::Array(params)) => params.iter().map(|p| p.as_integer().and_then(|p| u32::try_from(p).ok())).collect(),
+      _ => None,
+    };
+    if params.as_deref() != Some(&[Params::DEFAULT_M_COST, Params::DEFAULT_T_COST, Params::DEFAULT_P_COST]) {
+      return Err(Error::Kdf("The COSE KDF parameters are not those of a3".into()));
+    }
+    Ok(Some(Kdf { salt }))
+}
+
+/// Decrypt a COSE_Encrypt0 message, tagged or not. Nothing is returned unless the ciphertext and
+/// protected header authenticate, and a wrong key is an E_AUTH error.
+#[allow(deprecated)]
+pub fn decrypt(data: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    crypt_xchacha::check_key(key)?;
+    let message = parse(data)?;
+    let nonce: [u8; 24] = message.unprotected.iv.as_slice().try_into()
+      .map_err(|_| Error::Malformed("The COSE IV is not a 24 byte XChaCha20Poly1305 nonce".into()))?;
+    let aead = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+    message.decrypt_ciphertext(&[], || Error::Malformed("The COSE message has no ciphertext".into()), |ciphertext, aad| {
+      if ciphertext.len() < TAG_LEN {
+        return Err(Error::Truncated(format!("The COSE ciphertext is {} bytes, too short for a tag of {TAG_LEN} bytes", ciphertext.len())));
+      }
+      aead.decrypt(GenericArray::from_slice(&nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| Error::Auth("Failed to authenticate the COSE message".into()))
+    })
+}
+
+/// Decrypt an enchanter ciphertext in either format and encrypt its plaintext with the same key
+/// to a COSE_Encrypt0 message. With an expected validation string, a mismatch is an E_AUTH error
+/// and nothing is decrypted.
+pub fn export(data: &[u8], key: &[u8], expected: Option<&str>, kdf: Option<&Kdf>) -> Result<Vec<u8>, Error> {
+    let decrypted = crypt_xchacha::decrypt_validated(data, key, expected, 0)?;
+    encrypt(&decrypted.plaintext, key, kdf)
+}
+
+/// Convert a COSE_Encrypt0 message to the enchanter format: decrypt it with from_key and encrypt
+/// the plaintext with to_key, the same key when the message's KDF salt is TUR.
+pub fn import(data: &[u8], from_key: &[u8], to_key: &[u8], format: Format, suite: CipherSuite) -> Result<Encrypted, Error> {
+    let mut plaintext = decrypt(data, from_key)?;
+    let ciphertext = crypt_xchacha::seal_format_suite(&plaintext, to_key, format, suite);
+    plaintext.zeroize();
+    let ciphertext = ciphertext?;
+    let validation_string = crypt_xchacha::validation_string(to_key, &ciphertext);
+    Ok(Encrypted { ciphertext, validation_string })
+}
+
+/// Parse a message and check that it is XChaCha20Poly1305 with nothing critical we don't understand.
+fn parse(data: &[u8]) -> Result<CoseEncrypt0, Error> {
+    let message = if is_cose(data) { CoseEncrypt0::from_tagged_slice(data) } else { CoseEncrypt0::from_slice(data) };
+    let message = message.map_err(cose_error)?;
+    if message.protected.header.alg != Some(Algorithm::PrivateUse(XCHACHA20POLY1305)) {
+      return Err(Error::Malformed(format!("Unsupported COSE algorithm, only XChaCha20Poly1305 ({XCHACHA20POLY1305}) is supported")));
+    }
+    if !message.protected.header.crit.is_empty() {
+      return Err(Error::Malformed("COSE crit headers are not supported".into()));
+    }
+    Ok(message)
+}
+
+fn cose_error(e: CoseError) -> Error {
+    Error::Malformed(format!("Not a valid COSE_Encrypt0 message: {e}"))
+}
@@ -1,55 +1,615 @@
-use rand::TryRngCore;
-use rand::rngs::OsRng;
+use base64::prelude::*;
+#[cfg(feature = "std")]
+use rand::{rngs::OsRng, TryRngCore};
+use rand_core::TryCryptoRng;
+#[cfg(feature = "std")]
+use rayon::prelude::*;
 use sha3::{Shake256, digest::{Update, ExtendableOutput, XofReader}};
 use argon2::Argon2;
 use chacha20poly1305::{
     aead::{AeadInPlace, KeyInit},
     XChaCha20Poly1305,
 };
+use aes_gcm_siv::Aes256GcmSiv;
 #[allow(deprecated)]
 use chacha20poly1305::aead::generic_array::GenericArray;
 
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str::FromStr;
+#[cfg(feature = "std")]
 use std::fs::File;
-use std::io::{Read, Write};
+#[cfg(feature = "std")]
+use std::io::{Read, Seek, SeekFrom, Write};
+#[cfg(feature = "std")]
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use zeroize::Zeroize;
+
+use crate::error::Error;
+
 /// There are three arbitrary consants of sufficient length (46 bytes) used as fixed salts.
 /// One of them is a "public const" named "TUR", while the other two are
 /// a private constants used within this module named "MAH" and "DEP".
-#[allow(unused)]
 pub const TUR: &[u8] = b"fe3oUFSXweSdjiYDFssoMUgkZ7KfG8pu4PGEsd3aFJzrU3";
 /// The ENCHA constant is a fixed salt (46 bytes) used within the crypt_xchacha module
 /// during the second round of Argon2id.
-#[allow(unused)]
 const MAH: &[u8] = b"6uUfPu7Y22NaUZKqmzVufiMX8DcZJwrDwoBMpRhzcAc9LF";
 /// The DEP constant is a fixed salt (46 bytes) used within the crypt_xchacha module
 /// during the third round of Argon2id.
-#[allow(unused)]
 const DEP: &[u8] = b"fe3oUFSXweSdjiYDFssoMUgkZ7KfG8p8EhD16HmvkLZ5FB";
+/// The length of the nonce and tag in front of the ciphertext.
+pub const HEADER_LEN: usize = 40;
+/// The largest valid ciphertext file: XChaCha20Poly1305 can encrypt at most 2^38 - 64 bytes
+/// (256 GiB) under one nonce, plus the nonce and tag.
+pub const MAX_LEN: u64 = (1 << 38) - 64 + HEADER_LEN as u64;
+/// The magic bytes at the start of a chunked file.
+pub const CHUNKED_MAGIC: &[u8; 7] = b"ENCHNTR";
+/// The version of the chunked format.
+pub const CHUNKED_VERSION: u8 = 1;
+/// The length of the chunked header: magic, version, cipher suite, chunk size and nonce prefix.
+pub const CHUNKED_HEADER_LEN: usize = 32;
+/// The default chunk size of the chunked format, 1 MiB.
+pub const DEFAULT_CHUNK_SIZE: u32 = 1 << 20;
+/// The length of a Poly1305 or POLYVAL tag.
+pub(crate) const TAG_LEN: usize = 16;
+
+/// How a file is encrypted. New formats and tuning options may be added, so chunked formats are
+/// built with `Format::chunked` and `Format::threads` rather than a struct literal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Format {
+    /// nonce | tag | ciphertext, the original single buffer format.
+    Legacy,
+    /// A header followed by independently authenticated chunks, encrypted with the given number
+    /// of threads. 0 threads uses all cores. The output does not depend on the number of threads.
+    #[non_exhaustive]
+    Chunked { chunk_size: u32, threads: usize },
+}
+
+impl Format {
+    /// The chunked format with the given chunk size, encrypted on all cores.
+    pub fn chunked(chunk_size: u32) -> Format {
+        Format::Chunked { chunk_size, threads: 0 }
+    }
+
+    /// Set the number of threads a chunked format is encrypted with, 0 for all cores.
+    /// The legacy format is always encrypted on one thread and is returned unchanged.
+    pub fn threads(self, threads: usize) -> Format {
+        match self {
+          Format::Chunked { chunk_size, .. } => Format::Chunked { chunk_size, threads },
+          format => format,
+        }
+    }
+}
+
+/// The AEAD a chunked file is encrypted with, recorded in its header and selected automatically
+/// when decrypting. The single buffer format is always XChaCha20Poly1305. More suites may be
+/// added, so matches outside this crate need a wildcard arm.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CipherSuite {
+    #[default]
+    XChaCha20Poly1305,
+    /// AES-256-GCM-SIV, which stays secure if a nonce is ever repeated, apart from revealing
+    /// whether the same chunk was encrypted twice.
+    Aes256GcmSiv,
+}
+
+impl CipherSuite {
+    /// The cipher suite byte in the chunked header.
+    pub fn id(self) -> u8 {
+        match self {
+          CipherSuite::XChaCha20Poly1305 => 0,
+          CipherSuite::Aes256GcmSiv => 1,
+        }
+    }
+
+    /// The cipher suite of a header byte. Unknown suites are E_MALFORMED.
+    pub fn from_id(id: u8) -> Result<CipherSuite, Error> {
+        match id {
+          0 => Ok(CipherSuite::XChaCha20Poly1305),
+          1 => Ok(CipherSuite::Aes256GcmSiv),
+          _ => Err(Error::Malformed(format!("Unsupported cipher suite {id}"))),
+        }
+    }
+
+    /// The name used by the --cipher option.
+    pub fn name(self) -> &'static str {
+        match self {
+          CipherSuite::XChaCha20Poly1305 => "xchacha20poly1305",
+          CipherSuite::Aes256GcmSiv => "aes-256-gcm-siv",
+        }
+    }
+}
+
+impl FromStr for CipherSuite {
+    type Err = Error;
+
+    /// Parse a name from CipherSuite::name. Unknown names are E_USAGE.
+    fn from_str(name: &str) -> Result<CipherSuite, Error> {
+        match name {
+          "xchacha20poly1305" => Ok(CipherSuite::XChaCha20Poly1305),
+          "aes-256-gcm-siv" => Ok(CipherSuite::Aes256GcmSiv),
+          _ => Err(Error::Usage(format!("Unknown cipher {name}, use xchacha20poly1305 or aes-256-gcm-siv"))),
+        }
+    }
+}
 
 
 /// This "checks" function is a string comparison function to ensure that the ciphertext hasn't been
 /// tampered with and that the key material is correct. Supply the function with two hashes
-/// generated from the ciphertext_hash function.
-#[allow(unused)]
+/// generated from the ciphertext_hash function. Reporting a mismatch is left to the caller.
 pub fn checks(validate: &str, ciphertext_hash: &str) -> bool {
-    let result = validate == ciphertext_hash;
-    if result {
-      return true
+    validate == ciphertext_hash
+}
+
+/// Compare the validation string of a ciphertext with the expected one from enchanter.toml,
+/// returning an E_AUTH mismatch error carrying both when they differ.
+pub fn validate(found: &str, expected: &str) -> Result<(), Error> {
+    if checks(found, expected) {
+      Ok(())
+    } else {
+      Err(Error::Mismatch { found: found.to_string(), expected: expected.to_string() })
+    }
+}
+
+/// A ciphertext file split into its parts: a 24 byte nonce, a 16 byte tag, and the ciphertext.
+pub struct Envelope<'a> {
+    pub nonce: &'a [u8; 24],
+    pub tag: &'a [u8; 16],
+    pub ciphertext: &'a [u8],
+}
+
+/// Parse a ciphertext file without panicking on any input. Files shorter than the nonce and tag
+/// are E_TRUNCATED, and files longer than MAX_LEN are E_MALFORMED. The format has no length field,
+/// so any bytes appended to a valid file are part of the ciphertext and fail authentication as E_AUTH.
+pub fn parse(data: &[u8]) -> Result<Envelope<'_>, Error> {
+    if data.len() as u64 > MAX_LEN {
+      return Err(Error::Malformed(format!("The ciphertext is {} bytes, larger than the maximum of {MAX_LEN} bytes", data.len())));
+    }
+    let (nonce, rest) = data.split_first_chunk::<24>()
+      .ok_or_else(|| Error::Truncated(format!("The ciphertext is {} bytes, too short for a nonce and tag of {HEADER_LEN} bytes", data.len())))?;
+    let (tag, ciphertext) = rest.split_first_chunk::<16>()
+      .ok_or_else(|| Error::Truncated(format!("The ciphertext is {} bytes, too short for a nonce and tag of {HEADER_LEN} bytes", data.len())))?;
+    Ok(Envelope { nonce, tag, ciphertext })
+}
+
+/// Open a ciphertext file, returning it with its size and whether it is in the chunked format.
+/// The file is left at its start.
+#[cfg(feature = "std")]
+fn open_ciphertext(input_file: &str) -> Result<(File, u64, bool), Error> {
+    let read_error = |e: std::io::Error| Error::Io(format!("Failed to read {input_file}: {e}"));
+    let mut file = File::open(input_file).map_err(|e| Error::Io(format!("Failed to open the input file {input_file}: {e}")))?;
+    let size = file.metadata().map_err(read_error)?.len();
+    let mut magic = [0u8; CHUNKED_MAGIC.len()];
+    let read = (&mut file).take(magic.len() as u64).read(&mut magic).map_err(read_error)?;
+    file.seek(SeekFrom::Start(0)).map_err(read_error)?;
+    Ok((file, size, is_chunked(&magic[..read])))
+}
+
+/// Whether a file is in the chunked format. The header and size of a chunked file are checked
+/// as well, without reading its chunks, so a malformed one is an error before any key is derived.
+#[cfg(feature = "std")]
+pub fn is_chunked_file(input_file: &str) -> Result<bool, Error> {
+    let (mut file, size, chunked) = open_ciphertext(input_file)?;
+    if chunked {
+      let mut header = [0u8; CHUNKED_HEADER_LEN];
+      let read = crate::range::read_full(&mut file, &mut header).map_err(|e| Error::Io(format!("Failed to read {input_file}: {e}")))?;
+      chunk_count(&parse_chunked_header(&header[..read])?, size)?;
+    }
+    Ok(chunked)
+}
+
+/// Read a whole ciphertext file. The size is checked against the header before anything is
+/// allocated, so single buffer files larger than MAX_LEN and chunked files whose length doesn't
+/// fit their chunk size are refused without being read. Chunked files of any size can be
+/// decrypted without reading them whole with decrypt_chunked_file.
+#[cfg(feature = "std")]
+pub fn read_ciphertext(input_file: &str) -> Result<Vec<u8>, Error> {
+    let (file, size, chunked) = open_ciphertext(input_file)?;
+    let mut file = file.take(size);
+    let mut data = Vec::new();
+    if chunked {
+      let mut header = [0u8; CHUNKED_HEADER_LEN];
+      let read = crate::range::read_full(&mut file, &mut header).map_err(|e| Error::Io(format!("Failed to read {input_file}: {e}")))?;
+      chunk_count(&parse_chunked_header(&header[..read])?, size)?;
+      data.extend_from_slice(&header[..read]);
+    } else if size > MAX_LEN {
+      return Err(Error::Malformed(format!("{input_file} is {size} bytes, larger than the maximum of {MAX_LEN} bytes")));
+    }
+    data.reserve_exact(size as usize - data.len());
+    file.read_to_end(&mut data).map_err(|e| Error::Io(format!("Failed to read {input_file}: {e}")))?;
+    Ok(data)
+}
+
+/// A 32 byte encryption key, zeroized on drop.
+#[derive(Clone)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    pub fn new(bytes: [u8; 32]) -> Key {
+        Key(bytes)
+    }
+
+    /// Derive a key from a password and salt with a3, such as the TUR salt or a keyfile's salt.
+    pub fn from_password(password: &[u8], salt: &[u8]) -> Result<Key, Error> {
+        try_a3(password, salt).map(Key)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl TryFrom<&[u8]> for Key {
+    type Error = Error;
+
+    /// Keys that are not 32 bytes are an E_KDF error.
+    fn try_from(bytes: &[u8]) -> Result<Key, Error> {
+        check_key(bytes)?;
+        Ok(Key(bytes.try_into().unwrap_or_default()))
+    }
+}
+
+impl core::fmt::Debug for Key {
+    /// The key itself is never printed.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+impl Drop for Key {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Check that the key is the 32 bytes XChaCha20Poly1305 needs.
+pub(crate) fn check_key(key: &[u8]) -> Result<(), Error> {
+    if key.len() != 32 {
+      return Err(Error::Kdf(format!("The key is {} bytes instead of 32", key.len())));
+    }
+    Ok(())
+}
+
+/// Encrypt plaintext into a ciphertext file's contents: nonce | tag | ciphertext.
+/// A nonce is generated using 8 bytes of time data and 16 random bytes.
+#[cfg(feature = "std")]
+pub fn seal(plaintext: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    check_key(key)?;
+    let mut nonce = [0u8; 24];
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let timestamp_nanos = now.as_nanos();
+    nonce[0..8].copy_from_slice(&timestamp_nanos.to_le_bytes()[0..8]);
+    OsRng.try_fill_bytes(&mut nonce[8..24]).map_err(|e| Error::Failed(format!("Failed to generate a nonce: {e}")))?;
+    seal_with_nonce(plaintext, key, &nonce)
+}
+
+/// The same as seal, with a nonce of 24 bytes from the caller's RNG, for targets without std.
+pub fn seal_with_rng<R: TryCryptoRng + ?Sized>(plaintext: &[u8], key: &[u8], rng: &mut R) -> Result<Vec<u8>, Error> {
+    check_key(key)?;
+    let mut nonce = [0u8; 24];
+    rng.try_fill_bytes(&mut nonce).map_err(|e| Error::Failed(format!("Failed to generate a nonce: {e}")))?;
+    seal_with_nonce(plaintext, key, &nonce)
+}
+
+#[allow(deprecated)]
+fn seal_with_nonce(plaintext: &[u8], key: &[u8], nonce: &[u8; 24]) -> Result<Vec<u8>, Error> {
+    let aead = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+    let mut ciphertext = plaintext.to_vec();
+    let tag = aead.encrypt_in_place_detached(GenericArray::from_slice(nonce), &[], &mut ciphertext)
+        .map_err(|_| Error::Failed("Failed to encrypt the plaintext".into()))?;
+    let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    sealed.extend_from_slice(nonce);
+    sealed.extend_from_slice(&tag);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Parse and decrypt a ciphertext file's contents. No plaintext is returned unless
+/// the whole ciphertext authenticates.
+#[allow(deprecated)]
+pub fn open(data: &[u8], key: &[u8]) -> Result<Vec<u8>, Error> {
+    let envelope = parse(data)?;
+    check_key(key)?;
+    let aead = XChaCha20Poly1305::new(GenericArray::from_slice(key));
+    let mut plaintext = envelope.ciphertext.to_vec();
+    aead.decrypt_in_place_detached(GenericArray::from_slice(envelope.nonce), &[], &mut plaintext, GenericArray::from_slice(envelope.tag))
+        .map_err(|_| Error::Auth("Failed to authenticate the ciphertext".into()))?;
+    Ok(plaintext)
+}
+
+/// The parsed header of a chunked file.
+///
+/// ```text
+/// magic "ENCHNTR" (7) | version (1) | cipher suite (1) | chunk size, big endian (4) | nonce prefix (19)
+/// ```
+///
+/// Each chunk is followed by its tag, and the nonce of chunk i is the nonce prefix, i as a big endian
+/// u32, and a byte that is 1 for the last chunk and 0 otherwise, as in the STREAM construction.
+/// AES-256-GCM-SIV has 12 byte nonces, which use the first 7 bytes of the prefix.
+/// The whole header is the associated data of every chunk, so reordering, dropping or truncating
+/// chunks, or changing the header, fails authentication.
+pub struct ChunkedHeader<'a> {
+    pub bytes: &'a [u8; CHUNKED_HEADER_LEN],
+    pub suite: CipherSuite,
+    pub chunk_size: u32,
+    pub nonce_prefix: &'a [u8; 19],
+}
+
+/// Whether the data starts with the chunked format magic.
+pub fn is_chunked(data: &[u8]) -> bool {
+    data.starts_with(CHUNKED_MAGIC)
+}
+
+/// Parse the header of a chunked file without panicking on any input.
+pub fn parse_chunked_header(data: &[u8]) -> Result<ChunkedHeader<'_>, Error> {
+    let (bytes, _) = data.split_first_chunk::<CHUNKED_HEADER_LEN>()
+      .ok_or_else(|| Error::Truncated(format!("The ciphertext is {} bytes, too short for a chunked header of {CHUNKED_HEADER_LEN} bytes", data.len())))?;
+    if &bytes[..7] != CHUNKED_MAGIC {
+      return Err(Error::Malformed("The ciphertext is not in the chunked format".into()));
+    }
+    if bytes[7] != CHUNKED_VERSION {
+      return Err(Error::Malformed(format!("Unsupported chunked format version {}", bytes[7])));
+    }
+    let suite = CipherSuite::from_id(bytes[8])?;
+    let chunk_size = u32::from_be_bytes([bytes[9], bytes[10], bytes[11], bytes[12]]);
+    if chunk_size == 0 {
+      return Err(Error::Malformed("The chunk size is 0".into()));
+    }
+    let nonce_prefix = bytes[13..].first_chunk::<19>().ok_or_else(|| Error::Malformed("Invalid nonce prefix".into()))?;
+    Ok(ChunkedHeader { bytes, suite, chunk_size, nonce_prefix })
+}
+
+/// The number of chunks in a chunked file of the given total length, checking that every chunk
+/// holds at least a tag and that the chunk indexes fit the nonce.
+pub(crate) fn chunk_count(header: &ChunkedHeader<'_>, len: u64) -> Result<u64, Error> {
+    let body = len.saturating_sub(CHUNKED_HEADER_LEN as u64);
+    if body < TAG_LEN as u64 {
+      return Err(Error::Truncated(format!("The ciphertext has {body} bytes after the header, too short for a chunk")));
+    }
+    let stride = header.chunk_size as u64 + TAG_LEN as u64;
+    let chunks = body.div_ceil(stride);
+    if body - (chunks - 1) * stride < TAG_LEN as u64 {
+      return Err(Error::Malformed("The last chunk is too short for a tag".into()));
+    }
+    if chunks - 1 > u32::MAX as u64 {
+      return Err(Error::Malformed(format!("{chunks} chunks are more than the format allows")));
+    }
+    Ok(chunks)
+}
+
+/// Check the structure of a file's contents in either format before asking for a key.
+pub fn parse_any(data: &[u8]) -> Result<(), Error> {
+    if is_chunked(data) {
+      let header = parse_chunked_header(data)?;
+      chunk_count(&header, data.len() as u64)?;
     } else {
-      println!("{{\n  \"ERROR\": \"Ciphertext and/or password are not as expected. \
-        The supplied password, the enchanter.toml was wrong, or the file was tampered with.\",");
-      println!("  \"Found hash\": \"{}\",", validate);
-      println!("  \"Expected hash\": \"{}\",", ciphertext_hash);
-      return false
-    };
+      parse(data)?;
+    }
+    Ok(())
+}
+
+/// The nonce of a chunk: prefix | index | last flag.
+pub(crate) fn chunk_nonce(prefix: &[u8; 19], index: usize, last: bool) -> [u8; 24] {
+    let mut nonce = [0u8; 24];
+    nonce[..19].copy_from_slice(prefix);
+    nonce[19..23].copy_from_slice(&(index as u32).to_be_bytes());
+    nonce[23] = last as u8;
+    nonce
+}
+
+/// The AEAD of a cipher suite, keyed, for sealing and opening the chunks of a chunked file.
+pub(crate) enum ChunkCipher {
+    XChaCha20Poly1305(XChaCha20Poly1305),
+    /// Boxed, as the AES key schedule is much larger than the XChaCha20Poly1305 key.
+    Aes256GcmSiv(Box<Aes256GcmSiv>),
+}
+
+#[allow(deprecated)]
+impl ChunkCipher {
+    /// The cipher for a key that has already been checked to be 32 bytes.
+    pub(crate) fn new(suite: CipherSuite, key: &[u8]) -> ChunkCipher {
+        match suite {
+          CipherSuite::XChaCha20Poly1305 => ChunkCipher::XChaCha20Poly1305(XChaCha20Poly1305::new(GenericArray::from_slice(key))),
+          CipherSuite::Aes256GcmSiv => ChunkCipher::Aes256GcmSiv(Box::new(Aes256GcmSiv::new(GenericArray::from_slice(key)))),
+        }
+    }
+
+    /// Encrypt a chunk in place, returning its tag.
+    pub(crate) fn seal(&self, prefix: &[u8; 19], index: usize, last: bool, header: &[u8], chunk: &mut [u8]) -> Result<[u8; TAG_LEN], Error> {
+        let nonce = chunk_nonce(prefix, index, last);
+        let tag = match self {
+          ChunkCipher::XChaCha20Poly1305(aead) => aead.encrypt_in_place_detached(GenericArray::from_slice(&nonce), header, chunk),
+          ChunkCipher::Aes256GcmSiv(aead) => aead.encrypt_in_place_detached(GenericArray::from_slice(&short_nonce(&nonce)), header, chunk),
+        };
+        tag.map(Into::into).map_err(|_| Error::Failed("Failed to encrypt the plaintext".into()))
+    }
+
+    /// Authenticate and decrypt a chunk in place. A bad tag is E_AUTH.
+    pub(crate) fn open(&self, prefix: &[u8; 19], index: usize, last: bool, header: &[u8], chunk: &mut [u8], tag: &[u8]) -> Result<(), Error> {
+        let nonce = chunk_nonce(prefix, index, last);
+        let result = match self {
+          ChunkCipher::XChaCha20Poly1305(aead) => aead.decrypt_in_place_detached(GenericArray::from_slice(&nonce), header, chunk, GenericArray::from_slice(tag)),
+          ChunkCipher::Aes256GcmSiv(aead) => aead.decrypt_in_place_detached(GenericArray::from_slice(&short_nonce(&nonce)), header, chunk, GenericArray::from_slice(tag)),
+        };
+        result.map_err(|_| Error::Auth(format!("Failed to authenticate chunk {index} of the ciphertext")))
+    }
+}
+
+/// The 12 byte AES-256-GCM-SIV nonce of a chunk: the first 7 bytes of the prefix, the index and
+/// the last flag.
+fn short_nonce(nonce: &[u8; 24]) -> [u8; 12] {
+    let mut short = [0u8; 12];
+    short[..7].copy_from_slice(&nonce[..7]);
+    short[7..].copy_from_slice(&nonce[19..]);
+    short
+}
+
+/// Run the closure on every item, sequentially for 1 thread, or on the global rayon pool split
+/// into as many runs of items as the given number of threads (0 for all cores). The global pool
+/// is started once per process, so no threads are created per call.
+#[cfg(feature = "std")]
+fn for_each_chunk<'a, T, F>(items: Vec<T>, threads: usize, f: F) -> Result<(), Error>
+where
+    T: Send + 'a,
+    F: Fn(usize, T) -> Result<(), Error> + Sync + Send,
+{
+    if threads == 1 {
+      return items.into_iter().enumerate().try_for_each(|(i, item)| f(i, item));
+    }
+    if threads == 0 {
+      return items.into_par_iter().enumerate().try_for_each(|(i, item)| f(i, item));
+    }
+    let run = items.len().div_ceil(threads).max(1);
+    let mut items = items.into_iter().enumerate().peekable();
+    let mut runs = Vec::with_capacity(threads);
+    while items.peek().is_some() {
+      runs.push(items.by_ref().take(run).collect::<Vec<_>>());
+    }
+    runs.into_par_iter().try_for_each(|run| run.into_iter().try_for_each(|(i, item)| f(i, item)))
+}
+
+/// Without the std feature there is no thread pool, and the items are always run sequentially.
+#[cfg(not(feature = "std"))]
+fn for_each_chunk<T, F>(items: Vec<T>, _threads: usize, f: F) -> Result<(), Error>
+where
+    F: Fn(usize, T) -> Result<(), Error>,
+{
+    items.into_iter().enumerate().try_for_each(|(i, item)| f(i, item))
+}
+
+/// Encrypt plaintext into the chunked format, one chunk per thread at a time.
+#[cfg(feature = "std")]
+pub fn seal_chunked(plaintext: &[u8], key: &[u8], chunk_size: u32, threads: usize) -> Result<Vec<u8>, Error> {
+    seal_chunked_suite(plaintext, key, chunk_size, threads, CipherSuite::XChaCha20Poly1305)
+}
+
+/// The same as seal_chunked, with the given cipher suite.
+#[cfg(feature = "std")]
+pub fn seal_chunked_suite(plaintext: &[u8], key: &[u8], chunk_size: u32, threads: usize, suite: CipherSuite) -> Result<Vec<u8>, Error> {
+    let mut prefix = [0u8; 19];
+    OsRng.try_fill_bytes(&mut prefix).map_err(|e| Error::Failed(format!("Failed to generate a nonce: {e}")))?;
+    seal_chunked_with_prefix(plaintext, key, chunk_size, threads, suite, &prefix)
+}
+
+/// The same as seal_chunked, with a nonce prefix from the caller's RNG, for targets without std.
+pub fn seal_chunked_with_rng<R: TryCryptoRng + ?Sized>(plaintext: &[u8], key: &[u8], chunk_size: u32, threads: usize, rng: &mut R) -> Result<Vec<u8>, Error> {
+    seal_chunked_suite_with_rng(plaintext, key, chunk_size, threads, CipherSuite::XChaCha20Poly1305, rng)
+}
+
+/// The same as seal_chunked_suite, with a nonce prefix from the caller's RNG.
+pub fn seal_chunked_suite_with_rng<R: TryCryptoRng + ?Sized>(plaintext: &[u8], key: &[u8], chunk_size: u32, threads: usize, suite: CipherSuite, rng: &mut R) -> Result<Vec<u8>, Error> {
+    let mut prefix = [0u8; 19];
+    rng.try_fill_bytes(&mut prefix).map_err(|e| Error::Failed(format!("Failed to generate a nonce: {e}")))?;
+    seal_chunked_with_prefix(plaintext, key, chunk_size, threads, suite, &prefix)
+}
+
+/// Encrypt plaintext into the chunked format with the given nonce prefix.
+/// The nonce prefix must never be reused with the same key.
+pub(crate) fn seal_chunked_with_prefix(plaintext: &[u8], key: &[u8], chunk_size: u32, threads: usize, suite: CipherSuite, prefix: &[u8; 19]) -> Result<Vec<u8>, Error> {
+    check_key(key)?;
+    if chunk_size == 0 {
+      return Err(Error::Usage("The chunk size must be at least 1 byte".into()));
+    }
+    let size = chunk_size as usize;
+    let chunks = plaintext.len().div_ceil(size).max(1);
+    if chunks - 1 > u32::MAX as usize {
+      return Err(Error::Usage(format!("{chunks} chunks are more than the format allows, use a larger chunk size")));
+    }
+    let mut header = [0u8; CHUNKED_HEADER_LEN];
+    header[..7].copy_from_slice(CHUNKED_MAGIC);
+    header[7] = CHUNKED_VERSION;
+    header[8] = suite.id();
+    header[9..13].copy_from_slice(&chunk_size.to_be_bytes());
+    header[13..].copy_from_slice(prefix);
+
+    let mut sealed = vec![0u8; CHUNKED_HEADER_LEN + plaintext.len() + chunks * TAG_LEN];
+    sealed[..CHUNKED_HEADER_LEN].copy_from_slice(&header);
+    let cipher = ChunkCipher::new(suite, key);
+    let outputs: Vec<&mut [u8]> = sealed[CHUNKED_HEADER_LEN..].chunks_mut(size + TAG_LEN).collect();
+    for_each_chunk(outputs, threads, |i, output| {
+      let input = &plaintext[(i * size).min(plaintext.len())..((i + 1) * size).min(plaintext.len())];
+      let (buffer, tag) = output.split_at_mut(input.len());
+      buffer.copy_from_slice(input);
+      tag.copy_from_slice(&cipher.seal(prefix, i, i == chunks - 1, &header, buffer)?);
+      Ok(())
+    })?;
+    Ok(sealed)
+}
+
+/// Parse and decrypt a chunked file's contents. No plaintext is returned unless every chunk
+/// authenticates.
+pub fn open_chunked(data: &[u8], key: &[u8], threads: usize) -> Result<Vec<u8>, Error> {
+    let header = parse_chunked_header(data)?;
+    let chunks = chunk_count(&header, data.len() as u64)? as usize;
+    check_key(key)?;
+    let body = &data[CHUNKED_HEADER_LEN..];
+    let stride = header.chunk_size as usize + TAG_LEN;
+    let cipher = ChunkCipher::new(header.suite, key);
+    let mut buffer = body.to_vec();
+    let inputs: Vec<&mut [u8]> = buffer.chunks_mut(stride).collect();
+    let result = for_each_chunk(inputs, threads, |i, chunk| {
+      let (ciphertext, tag) = chunk.split_at_mut(chunk.len() - TAG_LEN);
+      cipher.open(header.nonce_prefix, i, i == chunks - 1, header.bytes, ciphertext, tag)
+    });
+    if let Err(e) = result {
+      buffer.zeroize();
+      return Err(e);
+    }
+    let mut plaintext = Vec::with_capacity(body.len() - chunks * TAG_LEN);
+    for chunk in buffer.chunks(stride) {
+      plaintext.extend_from_slice(&chunk[..chunk.len() - TAG_LEN]);
+    }
+    buffer.zeroize();
+    Ok(plaintext)
+}
+
+/// Encrypt plaintext in the given format.
+#[cfg(feature = "std")]
+pub fn seal_format(plaintext: &[u8], key: &[u8], format: Format) -> Result<Vec<u8>, Error> {
+    seal_format_suite(plaintext, key, format, CipherSuite::XChaCha20Poly1305)
+}
+
+/// Encrypt plaintext in the given format and cipher suite. The single buffer format has no header
+/// to record a suite in, so it is E_USAGE with anything but XChaCha20Poly1305.
+#[cfg(feature = "std")]
+pub fn seal_format_suite(plaintext: &[u8], key: &[u8], format: Format, suite: CipherSuite) -> Result<Vec<u8>, Error> {
+    match format {
+      Format::Legacy => {
+        check_legacy_suite(suite)?;
+        seal(plaintext, key)
+      },
+      Format::Chunked { chunk_size, threads } => seal_chunked_suite(plaintext, key, chunk_size, threads, suite),
+    }
+}
+
+/// The single buffer format is only XChaCha20Poly1305.
+#[cfg(feature = "std")]
+pub(crate) fn check_legacy_suite(suite: CipherSuite) -> Result<(), Error> {
+    if suite != CipherSuite::XChaCha20Poly1305 {
+      return Err(Error::Usage(format!("{} needs the chunked format", suite.name())));
+    }
+    Ok(())
+}
+
+/// Decrypt a file's contents in either format, using the given number of threads for chunked files.
+pub fn open_any(data: &[u8], key: &[u8], threads: usize) -> Result<Vec<u8>, Error> {
+    if is_chunked(data) {
+      open_chunked(data, key, threads)
+    } else {
+      open(data, key)
+    }
 }
 
 /// Generate key material with three rounds of Argon2id.
 /// The first round is based on the password and supplied salt.
 /// The second round is the output of the first round and the "MAH" salt.
 /// The third round is the output of the second round and the "DEP" salt.
-#[allow(unused)]
 pub fn a3(password: &[u8], salt: &[u8]) -> [u8; 32] {
     let mut okm = [0u8; 32];
     let mut rkm = [0u8; 32];
@@ -60,11 +620,23 @@ pub fn a3(password: &[u8], salt: &[u8]) -> [u8; 32] {
     zkm
 }
 
+/// The same as a3, but an Argon2 failure (such as a salt shorter than 8 bytes) is returned
+/// as an E_KDF error instead of silently producing a key.
+pub fn try_a3(password: &[u8], salt: &[u8]) -> Result<[u8; 32], Error> {
+    let kdf = |e: argon2::Error| Error::Kdf(format!("Argon2 key derivation failed: {e}"));
+    let mut okm = [0u8; 32];
+    let mut rkm = [0u8; 32];
+    let mut zkm = [0u8; 32];
+    Argon2::default().hash_password_into(password, salt, &mut okm).map_err(kdf)?;
+    Argon2::default().hash_password_into(MAH, &okm,  &mut rkm).map_err(kdf)?;
+    Argon2::default().hash_password_into(DEP, &rkm, &mut zkm).map_err(kdf)?;
+    Ok(zkm)
+}
+
 /// This function generates a SHA3 XOF with SHAKE 256.
 /// The XOF (hash) has input of the password and the ciphertext so
 /// that if either the password is incorrect or the ciphertext has been
 /// modified, the value will change.
-#[allow(unused)]
 pub fn ciphertext_hash(password: &[u8], file_data: &[u8], length: usize) -> Vec<u8> {
     let mut hasher = Shake256::default();
     hasher.update(password);
@@ -75,65 +647,240 @@ pub fn ciphertext_hash(password: &[u8], file_data: &[u8], length: usize) -> Vec<
     key
 }
 
+/// The base64 validation string (ciphertext_hash) of a whole ciphertext file for a key,
+/// as recorded in enchanter.toml.
+pub fn validation_string(key: &[u8], data: &[u8]) -> String {
+    BASE64_STANDARD.encode(ciphertext_hash(key, data, 64))
+}
+
+/// The result of decrypting a ciphertext file's contents: the plaintext, which is zeroized on drop,
+/// and the validation string computed from the same buffer. Fields may be added, so it is only
+/// built by the library.
+#[non_exhaustive]
+pub struct Decrypted {
+    pub plaintext: Vec<u8>,
+    pub validation_string: String,
+}
+
+impl Drop for Decrypted {
+    fn drop(&mut self) {
+        self.plaintext.zeroize();
+    }
+}
+
+/// The result of encrypting in memory: the same bytes as a ciphertext file, and their validation string.
+/// Fields may be added, so it is only built by the library.
+#[non_exhaustive]
+pub struct Encrypted {
+    pub ciphertext: Vec<u8>,
+    pub validation_string: String,
+}
+
+/// Encrypt plaintext in memory into the single buffer format, the same as encrypt_file writes.
+#[cfg(feature = "std")]
+pub fn encrypt_bytes(plaintext: &[u8], key: &Key) -> Result<Encrypted, Error> {
+    let ciphertext = seal(plaintext, key.as_bytes())?;
+    let validation_string = validation_string(key.as_bytes(), &ciphertext);
+    Ok(Encrypted { ciphertext, validation_string })
+}
+
+/// The same as encrypt_bytes, with a nonce from the caller's RNG, for targets without std.
+pub fn encrypt_bytes_with_rng<R: TryCryptoRng + ?Sized>(plaintext: &[u8], key: &Key, rng: &mut R) -> Result<Encrypted, Error> {
+    let ciphertext = seal_with_rng(plaintext, key.as_bytes(), rng)?;
+    let validation_string = validation_string(key.as_bytes(), &ciphertext);
+    Ok(Encrypted { ciphertext, validation_string })
+}
+
+/// Decrypt a ciphertext file's contents in memory, in either format.
+pub fn decrypt_bytes(data: &[u8], key: &Key) -> Result<Decrypted, Error> {
+    decrypt_validated(data, key.as_bytes(), None, 1)
+}
+
+/// Decrypt a ciphertext file's contents that have already been read, in either format, computing
+/// the validation string from the same buffer. With an expected validation string, a mismatch is
+/// an E_AUTH error and nothing is decrypted. Chunked files are decrypted with the given number of threads.
+pub fn decrypt_validated(data: &[u8], key: &[u8], expected: Option<&str>, threads: usize) -> Result<Decrypted, Error> {
+    let validation_string = validation_string(key, data);
+    if let Some(expected) = expected {
+      validate(&validation_string, expected)?;
+    }
+    let plaintext = open_any(data, key, threads)?;
+    Ok(Decrypted { plaintext, validation_string })
+}
+
+/// Encrypt a file, reading the plaintext once and hashing the ciphertext before it is written,
+/// and return the validation string of the output file.
+#[cfg(feature = "std")]
+pub fn encrypt_file_validated(input_file: &str, output_file: &str, key: &[u8]) -> Result<String, Error> {
+    encrypt_file_format(input_file, output_file, key, Format::Legacy)
+}
+
+/// The same as encrypt_file_validated, in the given format.
+#[cfg(feature = "std")]
+pub fn encrypt_file_format(input_file: &str, output_file: &str, key: &[u8], format: Format) -> Result<String, Error> {
+    encrypt_file_suite(input_file, output_file, key, format, CipherSuite::XChaCha20Poly1305)
+}
+
+/// The same as encrypt_file_format, in the given cipher suite.
+#[cfg(feature = "std")]
+pub fn encrypt_file_suite(input_file: &str, output_file: &str, key: &[u8], format: Format, suite: CipherSuite) -> Result<String, Error> {
+    let mut plaintext_file = File::open(input_file).map_err(|e| Error::Io(format!("Failed to open the input file {input_file}: {e}")))?;
+    let mut plaintext = Vec::new();
+    plaintext_file.read_to_end(&mut plaintext).map_err(|e| Error::Io(format!("Failed to read {input_file}: {e}")))?;
+    let sealed = seal_format_suite(&plaintext, key, format, suite);
+    plaintext.zeroize();
+    let sealed = sealed?;
+    let validation_string = validation_string(key, &sealed);
+    let mut output = File::create(output_file).map_err(|e| Error::Io(format!("Failed to create {output_file}: {e}")))?;
+    output.write_all(&sealed).map_err(|e| Error::Io(format!("Failed to write {output_file}: {e}")))?;
+    Ok(validation_string)
+}
+
+/// Decrypt a file, reading it once for both the validation and the decryption, and return
+/// the validation string. The output file is only created once the ciphertext validates.
+/// Chunked files are decrypted with decrypt_chunked_file instead of being read whole, and the
+/// output file is removed if a chunk fails to authenticate.
+#[cfg(feature = "std")]
+pub fn decrypt_file_validated(input_file: &str, output_file: &str, key: &[u8], expected: Option<&str>, threads: usize) -> Result<String, Error> {
+    let (mut file, size, chunked) = open_ciphertext(input_file)?;
+    if chunked {
+      check_key(key)?;
+      if let Some(expected) = expected {
+        validate(&hash_ciphertext(&mut file, size, key, input_file)?, expected)?;
+        file.seek(SeekFrom::Start(0)).map_err(|e| Error::Io(format!("Failed to read {input_file}: {e}")))?;
+      }
+      let mut output = File::create(output_file).map_err(|e| Error::Io(format!("Failed to create {output_file}: {e}")))?;
+      let result = open_chunked_stream(&mut file, size, &mut output, key, threads, input_file);
+      if result.is_err() {
+        drop(output);
+        let _ = std::fs::remove_file(output_file);
+      }
+      return result;
+    }
+    drop(file);
+    let data = read_ciphertext(input_file)?;
+    let decrypted = decrypt_validated(&data, key, expected, threads)?;
+    let mut plaintext_file = File::create(output_file).map_err(|e| Error::Io(format!("Failed to create {output_file}: {e}")))?;
+    plaintext_file.write_all(&decrypted.plaintext).map_err(|e| Error::Io(format!("Failed to write {output_file}: {e}")))?;
+    Ok(decrypted.validation_string.clone())
+}
+
+/// Decrypt a chunked file to the writer one batch of chunks at a time, a chunk per thread, so
+/// memory use depends on the chunk size and the number of threads rather than the file size.
+/// With an expected validation string the file is hashed first, and nothing is written unless it
+/// matches. Without one, a chunk that fails to authenticate stops the decryption after the chunks
+/// before it have been written. Returns the validation string.
+#[cfg(feature = "std")]
+pub fn decrypt_chunked_file<W: Write>(input_file: &str, output: &mut W, key: &[u8], expected: Option<&str>, threads: usize) -> Result<String, Error> {
+    let (mut file, size, _) = open_ciphertext(input_file)?;
+    check_key(key)?;
+    if let Some(expected) = expected {
+      validate(&hash_ciphertext(&mut file, size, key, input_file)?, expected)?;
+      file.seek(SeekFrom::Start(0)).map_err(|e| Error::Io(format!("Failed to read {input_file}: {e}")))?;
+    }
+    open_chunked_stream(&mut file, size, output, key, threads, input_file)
+}
+
+/// The validation string of a ciphertext file, hashed as it is read instead of reading it whole.
+#[cfg(feature = "std")]
+pub fn file_validation_string(input_file: &str, key: &[u8]) -> Result<String, Error> {
+    let (mut file, size, _) = open_ciphertext(input_file)?;
+    hash_ciphertext(&mut file, size, key, input_file)
+}
+
+/// Hash the first size bytes of the input into a validation string.
+#[cfg(feature = "std")]
+fn hash_ciphertext<R: Read>(input: &mut R, size: u64, key: &[u8], input_file: &str) -> Result<String, Error> {
+    let mut hasher = crate::stream::validator(key);
+    let mut input = input.take(size);
+    let mut buffer = vec![0u8; crate::stream::READ_SIZE];
+    loop {
+      match input.read(&mut buffer) {
+        Ok(0) => break,
+        Ok(n) => hasher.update(&buffer[..n]),
+        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+        Err(e) => return Err(Error::Io(format!("Failed to read {input_file}: {e}"))),
+      }
+    }
+    Ok(crate::stream::finish_validator(hasher))
+}
+
+/// Decrypt the first size bytes of a chunked input to the writer, reading as many chunks at a
+/// time as there are threads, and return the validation string of the input.
+#[cfg(feature = "std")]
+fn open_chunked_stream<R: Read, W: Write>(input: &mut R, size: u64, output: &mut W, key: &[u8], threads: usize, input_file: &str) -> Result<String, Error> {
+    let read_error = |e: std::io::Error| Error::Io(format!("Failed to read {input_file}: {e}"));
+    let write_error = |e: std::io::Error| Error::Io(format!("Failed to write the plaintext: {e}"));
+    let mut input = input.take(size);
+    let mut bytes = [0u8; CHUNKED_HEADER_LEN];
+    let read = crate::range::read_full(&mut input, &mut bytes).map_err(read_error)?;
+    let header = parse_chunked_header(&bytes[..read])?;
+    let chunks = chunk_count(&header, size)?;
+    let cipher = ChunkCipher::new(header.suite, key);
+    let mut hasher = crate::stream::validator(key);
+    hasher.update(header.bytes);
+
+    let stride = header.chunk_size as u64 + TAG_LEN as u64;
+    let batch = if threads == 0 { rayon::current_num_threads() as u64 } else { threads as u64 };
+    let mut remaining = size - CHUNKED_HEADER_LEN as u64;
+    let mut buffer = Vec::new();
+    let mut index = 0;
+    while remaining > 0 {
+      let len = (batch * stride).min(remaining);
+      buffer.resize(len as usize, 0);
+      let result = input.read_exact(&mut buffer).map_err(read_error).and_then(|_| {
+        hasher.update(&buffer);
+        let first = index;
+        let inputs: Vec<&mut [u8]> = buffer.chunks_mut(stride as usize).collect();
+        index += inputs.len();
+        for_each_chunk(inputs, threads, |i, chunk| {
+          let (ciphertext, tag) = chunk.split_at_mut(chunk.len() - TAG_LEN);
+          cipher.open(header.nonce_prefix, first + i, (first + i) as u64 == chunks - 1, header.bytes, ciphertext, tag)
+        })?;
+        buffer.chunks(stride as usize).try_for_each(|chunk| output.write_all(&chunk[..chunk.len() - TAG_LEN])).map_err(write_error)
+      });
+      if let Err(e) = result {
+        buffer.zeroize();
+        return Err(e);
+      }
+      remaining -= len;
+    }
+    buffer.zeroize();
+    output.flush().map_err(write_error)?;
+    Ok(crate::stream::finish_validator(hasher))
+}
+
 /// Encrypt a file with XChaCha20Poly1305. The function takes an input file, and output, and key to use for
 /// the encryption. A nonce is generated using 8 bytes of time data and 16 random bytes.
-#[allow(unused)]
-#[allow(deprecated)]
-pub fn encrypt_file(input_file: &str, output_file: &str, key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+#[cfg(feature = "std")]
+pub fn encrypt_file(input_file: &str, output_file: &str, key: &[u8]) -> Result<(), Error> {
+    let key = Key::try_from(key)?;
     let mut plaintext_file = File::open(input_file)?;
     let mut plaintext = Vec::new();
     plaintext_file.read_to_end(&mut plaintext)?;
-    let mut nonce = [0u8; 24];
-    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-    let timestamp_nanos = now.as_nanos();
-    nonce[0..8].copy_from_slice(&timestamp_nanos.to_le_bytes()[0..8]);
-    OsRng.try_fill_bytes(&mut nonce[8..24]);
-    let aead = XChaCha20Poly1305::new(GenericArray::from_slice(key));
-    let mut ciphertext_file = File::create(output_file)?;
-    let mut ciphertext = plaintext.to_vec();
-    let tag = aead.encrypt_in_place_detached(&nonce.into(), &[], &mut ciphertext);
-    let wtag: &[u8] = &tag.unwrap();
+    let encrypted = encrypt_bytes(&plaintext, &key);
+    plaintext.zeroize();
     let mut output = File::create(output_file)?;
-    output.write_all(&nonce)?;
-    output.write_all(wtag)?;
-    output.write_all(&ciphertext)?;
+    output.write_all(&encrypted?.ciphertext)?;
     Ok(())
 }
 
-/// Decrypt a file with XChaCha20Poly1305.
-#[allow(unused)]
-#[allow(deprecated)]
-pub fn decrypt_file(input_file: &str, output_file: &str, key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
-    let mut ciphertext_file = File::open(input_file)?;
-    let mut ciphertext = Vec::new();
-    ciphertext_file.read_to_end(&mut ciphertext)?;
-    let nonce = chacha20poly1305::XNonce::from_slice(&ciphertext[..24]);
-    let tag = GenericArray::clone_from_slice(&ciphertext[24..40]);
-    let mut plaintext = ciphertext[40..].to_vec();
-    let aead = XChaCha20Poly1305::new(GenericArray::from_slice(key));
-    aead.decrypt_in_place_detached(nonce, &[], &mut plaintext, &tag);
-
-    let mut plaintext_file = File::create(output_file)?;
-    plaintext_file.write_all(&plaintext)?;
-    Ok(())
+/// Decrypt a file with XChaCha20Poly1305. The output file is only created once the ciphertext authenticates,
+/// or for a chunked file, removed if a chunk fails to authenticate.
+#[cfg(feature = "std")]
+pub fn decrypt_file(input_file: &str, output_file: &str, key: &[u8]) -> Result<(), Error> {
+    decrypt_file_validated(input_file, output_file, key, None, 1).map(|_| ())
 }
 
 /// Decrypt a file to STDOUT.
 /// The output is any UTF-8 data. If the data is non-UTF-8,
 /// decrypt to a file instead with the decrypt_file function.
-#[allow(unused)]
-#[allow(deprecated)]
-pub fn decrypt_stdout(input_file: &str, key: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
-    let mut ciphertext_file = File::open(input_file)?;
-    let mut ciphertext = Vec::new();
-    ciphertext_file.read_to_end(&mut ciphertext)?;
-    let nonce = chacha20poly1305::XNonce::from_slice(&ciphertext[..24]);
-    let tag = GenericArray::clone_from_slice(&ciphertext[24..40]);
-    let mut plaintext = ciphertext[40..].to_vec();
-    let aead = XChaCha20Poly1305::new(GenericArray::from_slice(key));
-    aead.decrypt_in_place_detached(nonce, &[], &mut plaintext, &tag);
+#[cfg(feature = "std")]
+pub fn decrypt_stdout(input_file: &str, key: &[u8]) -> Result<(), Error> {
+    let ciphertext = read_ciphertext(input_file)?;
+    let decrypted = decrypt_bytes(&ciphertext, &Key::try_from(key)?)?;
 
-    println!("{}", String::from_utf8_lossy(&plaintext));
+    println!("{}", String::from_utf8_lossy(&decrypted.plaintext));
 
     Ok(())
 }
@@ -0,0 +1,143 @@
+//! Reading files written by enchantress, the AES sibling of enchanter, behind the `enchantress` feature.
+//!
+//! enchantress derives its key with a2, two rounds of Argon2id with its own salts rather than the
+//! three rounds of a3, so the same password gives a different key. Each encryption records the
+//! same SHAKE256 validation string as enchanter in an enchantress.toml, along with the mode:
+//!
+//! ```toml
+//! ciphertext_path = "sample.ctr.e"
+//! ciphertext_hash = "T4g9bqqCoBfoAr2H1pszaXVKvRKevwDV3GW1DHOq/hFGsOwV+yXbBy8lDFixhU7rUjVQlzdkurAMPIqDRtAVvw=="
+//! creation_time = "2026-10-19 03:21:11.180107597 UTC"
+//! mode = "CTR"
+//! ```
+//!
+//! - CTR, the default and the only mode before the mode field was added: a 16 byte nonce, then
+//!   the plaintext XORed with the AES-256-CTR keystream (a 64 bit big endian counter).
+//! - GCM, from `-ge`: a 12 byte nonce, then the plaintext itself. enchantress discards the output
+//!   of AES-256-GCM, so these files were never encrypted.
+//!
+//! Neither mode has an authentication tag, so decryption always needs the validation string.
+
+use aes::Aes256;
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use ctr::Ctr64BE;
+use serde::Deserialize;
+
+use std::fs;
+use std::io;
+
+use crate::crypt_xchacha::{self, CipherSuite, Decrypted, Encrypted, Format};
+use crate::error::Error;
+
+/// The name of the manifest enchantress keeps in its working directory.
+pub const MANIFEST_FILE_NAME: &str = "enchantress.toml";
+
+/// The salt of the first a2 round, the password salt of the enchantress command.
+pub const MAGIC: &[u8] = b"789c33a8303536333437323334b328353301001ccc0395";
+/// The salt of the second a2 round.
+const ENCHA: &[u8] = b"789c33a8303132733337373335732d353301001df903be";
+
+type Aes256Ctr = Ctr64BE<Aes256>;
+
+/// Derive a key with the two Argon2id rounds of enchantress. An Argon2 failure is an E_KDF error.
+pub fn a2(password: &[u8], salt: &[u8]) -> Result<[u8; 32], Error> {
+    let kdf = |e: argon2::Error| Error::Kdf(format!("Argon2 key derivation failed: {e}"));
+    let mut okm = [0u8; 32];
+    let mut rkm = [0u8; 32];
+    argon2::Argon2::default().hash_password_into(password, salt, &mut okm).map_err(kdf)?;
+    argon2::Argon2::default().hash_password_into(ENCHA, &okm, &mut rkm).map_err(kdf)?;
+    Ok(rkm)
+}
+
+/// Derive a key from a password the way the enchantress command does.
+pub fn derive_key(password: &[u8]) -> Result<[u8; 32], Error> {
+    a2(password, MAGIC)
+}
+
+/// How an enchantress file was written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Ctr,
+    Gcm,
+}
+
+impl Mode {
+    /// The name used in enchantress.toml.
+    pub fn name(self) -> &'static str {
+        match self {
+          Mode::Ctr => "CTR",
+          Mode::Gcm => "GCM",
+        }
+    }
+
+    /// The length of the nonce at the start of the file.
+    fn nonce_len(self) -> usize {
+        match self {
+          Mode::Ctr => 16,
+          Mode::Gcm => 12,
+        }
+    }
+}
+
+/// The contents of an enchantress.toml.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub ciphertext_path: Option<String>,
+    /// The validation string of the ciphertext.
+    pub ciphertext_hash: String,
+    #[serde(default)]
+    pub creation_time: Option<String>,
+    /// CTR or GCM, missing in files from older versions of enchantress.
+    #[serde(default)]
+    pub mode: Option<String>,
+}
+
+impl Manifest {
+    /// Read an enchantress.toml. A missing file is E_MANIFEST_MISSING and one that can't be parsed,
+    /// or has an unknown mode, is E_MANIFEST_INVALID.
+    pub fn read(path: &str) -> Result<Manifest, Error> {
+        let contents = fs::read_to_string(path).map_err(|e| match e.kind() {
+          io::ErrorKind::NotFound => Error::ManifestMissing(format!("No {path} found")),
+          _ => Error::Io(format!("Failed to read {path}: {e}")),
+        })?;
+        let manifest: Manifest = toml::from_str(&contents).map_err(|_| Error::ManifestInvalid(format!("Failed to parse {path}")))?;
+        manifest.mode()?;
+        Ok(manifest)
+    }
+
+    /// The mode of the file, CTR when the manifest does not say.
+    pub fn mode(&self) -> Result<Mode, Error> {
+        match self.mode.as_deref() {
+          None | Some("CTR") => Ok(Mode::Ctr),
+          Some("GCM") => Ok(Mode::Gcm),
+          Some(other) => Err(Error::ManifestInvalid(format!("Unknown enchantress mode {other}"))),
+        }
+    }
+}
+
+/// Decrypt the contents of an enchantress file. The validation string is checked first, as the
+/// only protection these files have, and a mismatch is an E_AUTH error.
+pub fn decrypt_validated(data: &[u8], key: &[u8], expected: &str, mode: Mode) -> Result<Decrypted, Error> {
+    crypt_xchacha::check_key(key)?;
+    if data.len() < mode.nonce_len() {
+      return Err(Error::Truncated(format!("An enchantress {} file starts with a {} byte nonce", mode.name(), mode.nonce_len())));
+    }
+    let validation_string = crypt_xchacha::validation_string(key, data);
+    crypt_xchacha::validate(&validation_string, expected)?;
+    let (nonce, body) = data.split_at(mode.nonce_len());
+    let mut plaintext = body.to_vec();
+    if mode == Mode::Ctr {
+      Aes256Ctr::new(key.into(), nonce.into()).apply_keystream(&mut plaintext);
+    }
+    Ok(Decrypted { plaintext, validation_string })
+}
+
+/// Convert an enchantress file to the enchanter format: decrypt it with the enchantress key,
+/// checked against its manifest, and encrypt the plaintext with the enchanter key.
+pub fn import(data: &[u8], manifest: &Manifest, from_key: &[u8], to_key: &[u8], format: Format, suite: CipherSuite) -> Result<Encrypted, Error> {
+    let decrypted = decrypt_validated(data, from_key, &manifest.ciphertext_hash, manifest.mode()?)?;
+    let ciphertext = crypt_xchacha::seal_format_suite(&decrypted.plaintext, to_key, format, suite)?;
+    let validation_string = crypt_xchacha::validation_string(to_key, &ciphertext);
+    Ok(Encrypted { ciphertext, validation_string })
+}
@@ -0,0 +1,100 @@
+//! The error type of enchanter, with a stable error code and process exit code for each kind of failure.
+//!
+//! | exit code | error code           | meaning                                                        |
+//! |-----------|----------------------|----------------------------------------------------------------|
+//! | 0         |                      | success                                                        |
+//! | 1         | `E_FAILED`           | any other failure                                              |
+//! | 2         | `E_USAGE`            | invalid arguments, options or environment variables            |
+//! | 3         | `E_AUTH`             | wrong password, wrong enchanter.toml, or tampered ciphertext   |
+//! | 4         | `E_MANIFEST_MISSING` | no enchanter.toml in the working directory                     |
+//! | 5         | `E_MANIFEST_INVALID` | the enchanter.toml could not be parsed                         |
+//! | 6         | `E_KDF`              | Argon2 key derivation failed                                   |
+//! | 7         | `E_IO`               | reading or writing a file failed                               |
+//! | 8         | `E_TRUNCATED`        | the ciphertext is too short to hold a nonce and tag            |
+//! | 11        | `E_MALFORMED`        | the ciphertext is not a valid enchanter file                   |
+//! | 12        | `E_CANCELLED`        | an async transfer was cancelled                                |
+//!
+//! Exit codes 9 (`E_KEYFILE`) and 10 (`E_WEAK_PASSWORD`) belong to failures of the enchanter
+//! command itself, and are never returned by the library.
+
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+/// An enchanter failure. Each variant carries a human readable message.
+/// New kinds of failure may be added, so matches need a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    Failed(String),
+    Usage(String),
+    Auth(String),
+    /// The validation string of the ciphertext and key did not match the expected one.
+    Mismatch { found: String, expected: String },
+    ManifestMissing(String),
+    ManifestInvalid(String),
+    Kdf(String),
+    Io(String),
+    Truncated(String),
+    Malformed(String),
+    /// Only produced by the async library API.
+    Cancelled(String),
+}
+
+impl Error {
+    /// The stable error identifier.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Failed(_) => "E_FAILED",
+            Error::Usage(_) => "E_USAGE",
+            Error::Auth(_) | Error::Mismatch { .. } => "E_AUTH",
+            Error::ManifestMissing(_) => "E_MANIFEST_MISSING",
+            Error::ManifestInvalid(_) => "E_MANIFEST_INVALID",
+            Error::Kdf(_) => "E_KDF",
+            Error::Io(_) => "E_IO",
+            Error::Truncated(_) => "E_TRUNCATED",
+            Error::Malformed(_) => "E_MALFORMED",
+            Error::Cancelled(_) => "E_CANCELLED",
+        }
+    }
+
+    /// The process exit code.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Failed(_) => 1,
+            Error::Usage(_) => 2,
+            Error::Auth(_) | Error::Mismatch { .. } => 3,
+            Error::ManifestMissing(_) => 4,
+            Error::ManifestInvalid(_) => 5,
+            Error::Kdf(_) => 6,
+            Error::Io(_) => 7,
+            Error::Truncated(_) => 8,
+            Error::Malformed(_) => 11,
+            Error::Cancelled(_) => 12,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Mismatch { .. } => write!(
+                f,
+                "Ciphertext and/or password are not as expected. The supplied password was wrong, the enchanter.toml was wrong, or the file was tampered with. Refusing to decrypt."
+            ),
+            Error::Failed(m) | Error::Usage(m) | Error::Auth(m) | Error::ManifestMissing(m) | Error::ManifestInvalid(m)
+            | Error::Kdf(m) | Error::Io(m) | Error::Truncated(m) | Error::Malformed(m)
+            | Error::Cancelled(m) => write!(f, "{m}"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e.to_string())
+    }
+}
@@ -0,0 +1,326 @@
+//! The C ABI, behind the `ffi` feature. The header is include/enchanter.h, generated by cbindgen.
+//!
+//! Every function returns `ENCHANTER_OK` or an error code, which is the same as the exit code of
+//! the enchanter command for that failure, and never unwinds into C: a panic is `ENCHANTER_E_PANIC`.
+//! Keys and output buffers are opaque handles owned by the caller, and must be released with
+//! `enchanter_key_free`, `enchanter_buffer_free` and `enchanter_string_free`, which zeroize them.
+//! Output pointers are only written on success.
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use zeroize::Zeroize;
+
+use crate::crypt_xchacha::{self, Format, Key, TUR};
+use crate::error::Error;
+
+pub const ENCHANTER_OK: i32 = 0;
+pub const ENCHANTER_E_FAILED: i32 = 1;
+/// Also returned for a null pointer that is required, or a string that is not UTF-8.
+pub const ENCHANTER_E_USAGE: i32 = 2;
+pub const ENCHANTER_E_AUTH: i32 = 3;
+pub const ENCHANTER_E_MANIFEST_MISSING: i32 = 4;
+pub const ENCHANTER_E_MANIFEST_INVALID: i32 = 5;
+pub const ENCHANTER_E_KDF: i32 = 6;
+pub const ENCHANTER_E_IO: i32 = 7;
+pub const ENCHANTER_E_TRUNCATED: i32 = 8;
+pub const ENCHANTER_E_MALFORMED: i32 = 11;
+pub const ENCHANTER_E_CANCELLED: i32 = 12;
+/// The library panicked. This is a bug.
+pub const ENCHANTER_E_PANIC: i32 = -1;
+
+/// The chunk size the enchanter command uses for --chunked, 1 MiB.
+pub const ENCHANTER_DEFAULT_CHUNK_SIZE: u32 = 1 << 20;
+
+/// A 32 byte key, zeroized when freed.
+pub struct EnchanterKey(Key);
+
+/// Bytes returned by the library, zeroized when freed.
+pub struct EnchanterBuffer(Vec<u8>);
+
+/// Run the body, turning its error or panic into an error code.
+fn guard<F: FnOnce() -> Result<(), Error>>(body: F) -> i32 {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+      Ok(Ok(())) => ENCHANTER_OK,
+      Ok(Err(e)) => e.exit_code(),
+      Err(_) => ENCHANTER_E_PANIC,
+    }
+}
+
+fn invalid(what: &str) -> Error {
+    Error::Usage(format!("Invalid {what}"))
+}
+
+/// A byte slice from a pointer and length. A null pointer is only accepted for a length of 0.
+unsafe fn bytes<'a>(data: *const u8, len: usize, what: &str) -> Result<&'a [u8], Error> {
+    if len == 0 {
+      return Ok(&[]);
+    }
+    if data.is_null() {
+      return Err(invalid(what));
+    }
+    Ok(unsafe { slice::from_raw_parts(data, len) })
+}
+
+/// A UTF-8 string from a NUL terminated C string, or None for a null pointer.
+unsafe fn string<'a>(s: *const c_char, what: &str) -> Result<Option<&'a str>, Error> {
+    if s.is_null() {
+      return Ok(None);
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().map(Some).map_err(|_| invalid(what))
+}
+
+unsafe fn key<'a>(key: *const EnchanterKey) -> Result<&'a Key, Error> {
+    unsafe { key.as_ref() }.map(|key| &key.0).ok_or_else(|| invalid("key"))
+}
+
+/// Write a value to an output pointer that has been checked to be non-null.
+unsafe fn put<T>(out: *mut T, value: T) {
+    unsafe { out.write(value) };
+}
+
+fn into_c_string(s: String) -> Result<*mut c_char, Error> {
+    CString::new(s).map(CString::into_raw).map_err(|_| Error::Failed("The string contains a NUL byte".into()))
+}
+
+/// Derive a key from a password and salt with the three Argon2id rounds of a3.
+///
+/// # Safety
+/// password and salt must point to password_len and salt_len readable bytes, and out must be a
+/// valid pointer to write the key handle to.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_a3(password: *const u8, password_len: usize, salt: *const u8, salt_len: usize, out: *mut *mut EnchanterKey) -> i32 {
+    guard(|| {
+      let password = unsafe { bytes(password, password_len, "password")? };
+      let salt = unsafe { bytes(salt, salt_len, "salt")? };
+      if out.is_null() {
+        return Err(invalid("output pointer"));
+      }
+      let key = Key::from_password(password, salt)?;
+      unsafe { put(out, Box::into_raw(Box::new(EnchanterKey(key)))) };
+      Ok(())
+    })
+}
+
+/// Derive a key from a password the way the enchanter command does, so files can be shared with it.
+///
+/// # Safety
+/// password must point to password_len readable bytes, and out must be a valid pointer to write
+/// the key handle to.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_derive_key(password: *const u8, password_len: usize, out: *mut *mut EnchanterKey) -> i32 {
+    unsafe { enchanter_a3(password, password_len, TUR.as_ptr(), TUR.len(), out) }
+}
+
+/// Make a key handle from 32 bytes of key material.
+///
+/// # Safety
+/// bytes must point to 32 readable bytes, and out must be a valid pointer to write the key handle to.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_key_from_bytes(bytes: *const u8, out: *mut *mut EnchanterKey) -> i32 {
+    guard(|| {
+      if bytes.is_null() || out.is_null() {
+        return Err(invalid("pointer"));
+      }
+      let key = Key::try_from(unsafe { slice::from_raw_parts(bytes, 32) })?;
+      unsafe { put(out, Box::into_raw(Box::new(EnchanterKey(key)))) };
+      Ok(())
+    })
+}
+
+/// Zeroize and free a key handle. A null pointer is ignored.
+///
+/// # Safety
+/// key must be null or a handle from this library that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_key_free(key: *mut EnchanterKey) {
+    if !key.is_null() {
+      drop(unsafe { Box::from_raw(key) });
+    }
+}
+
+/// Encrypt a buffer into the single buffer format, the same bytes as an encrypted file.
+/// The validation string is written to validation_string unless it is null.
+///
+/// # Safety
+/// key must be a valid key handle, plaintext must point to plaintext_len readable bytes, out must be
+/// a valid pointer, and validation_string must be null or a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_encrypt(key: *const EnchanterKey, plaintext: *const u8, plaintext_len: usize, out: *mut *mut EnchanterBuffer, validation_string: *mut *mut c_char) -> i32 {
+    guard(|| {
+      let key = unsafe { self::key(key)? };
+      let plaintext = unsafe { bytes(plaintext, plaintext_len, "plaintext")? };
+      if out.is_null() {
+        return Err(invalid("output pointer"));
+      }
+      let encrypted = crypt_xchacha::encrypt_bytes(plaintext, key)?;
+      if !validation_string.is_null() {
+        unsafe { put(validation_string, into_c_string(encrypted.validation_string)?) };
+      }
+      unsafe { put(out, Box::into_raw(Box::new(EnchanterBuffer(encrypted.ciphertext)))) };
+      Ok(())
+    })
+}
+
+/// Decrypt a buffer in either format. With a non-null expected validation string, a mismatch is
+/// ENCHANTER_E_AUTH and nothing is decrypted.
+///
+/// # Safety
+/// key must be a valid key handle, ciphertext must point to ciphertext_len readable bytes, expected
+/// must be null or a NUL terminated string, and out must be a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_decrypt(key: *const EnchanterKey, ciphertext: *const u8, ciphertext_len: usize, expected: *const c_char, out: *mut *mut EnchanterBuffer) -> i32 {
+    guard(|| {
+      let key = unsafe { self::key(key)? };
+      let ciphertext = unsafe { bytes(ciphertext, ciphertext_len, "ciphertext")? };
+      let expected = unsafe { string(expected, "validation string")? };
+      if out.is_null() {
+        return Err(invalid("output pointer"));
+      }
+      let mut decrypted = crypt_xchacha::decrypt_validated(ciphertext, key.as_bytes(), expected, 1)?;
+      let plaintext = std::mem::take(&mut decrypted.plaintext);
+      unsafe { put(out, Box::into_raw(Box::new(EnchanterBuffer(plaintext)))) };
+      Ok(())
+    })
+}
+
+/// Encrypt a file. A chunk_size of 0 writes the single buffer format, anything else the chunked
+/// format with chunks of that size. The validation string is written to validation_string unless
+/// it is null.
+///
+/// # Safety
+/// key must be a valid key handle, input and output must be NUL terminated paths, and
+/// validation_string must be null or a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_encrypt_file(key: *const EnchanterKey, input: *const c_char, output: *const c_char, chunk_size: u32, validation_string: *mut *mut c_char) -> i32 {
+    guard(|| {
+      let key = unsafe { self::key(key)? };
+      let input = unsafe { string(input, "input path")? }.ok_or_else(|| invalid("input path"))?;
+      let output = unsafe { string(output, "output path")? }.ok_or_else(|| invalid("output path"))?;
+      let format = match chunk_size {
+        0 => Format::Legacy,
+        chunk_size => Format::chunked(chunk_size),
+      };
+      let found = crypt_xchacha::encrypt_file_format(input, output, key.as_bytes(), format)?;
+      if !validation_string.is_null() {
+        unsafe { put(validation_string, into_c_string(found)?) };
+      }
+      Ok(())
+    })
+}
+
+/// Decrypt a file in either format. With a non-null expected validation string, a mismatch is
+/// ENCHANTER_E_AUTH and the output file is not created. The validation string is written to
+/// validation_string unless it is null.
+///
+/// # Safety
+/// key must be a valid key handle, input and output must be NUL terminated paths, expected must be
+/// null or a NUL terminated string, and validation_string must be null or a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_decrypt_file(key: *const EnchanterKey, input: *const c_char, output: *const c_char, expected: *const c_char, validation_string: *mut *mut c_char) -> i32 {
+    guard(|| {
+      let key = unsafe { self::key(key)? };
+      let input = unsafe { string(input, "input path")? }.ok_or_else(|| invalid("input path"))?;
+      let output = unsafe { string(output, "output path")? }.ok_or_else(|| invalid("output path"))?;
+      let expected = unsafe { string(expected, "validation string")? };
+      let found = crypt_xchacha::decrypt_file_validated(input, output, key.as_bytes(), expected, 0)?;
+      if !validation_string.is_null() {
+        unsafe { put(validation_string, into_c_string(found)?) };
+      }
+      Ok(())
+    })
+}
+
+/// Compute the validation string (the enchanter.toml ciphertext_hash) of a ciphertext for a key.
+///
+/// # Safety
+/// key must be a valid key handle, ciphertext must point to ciphertext_len readable bytes, and out
+/// must be a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_validation_string(key: *const EnchanterKey, ciphertext: *const u8, ciphertext_len: usize, out: *mut *mut c_char) -> i32 {
+    guard(|| {
+      let key = unsafe { self::key(key)? };
+      let ciphertext = unsafe { bytes(ciphertext, ciphertext_len, "ciphertext")? };
+      if out.is_null() {
+        return Err(invalid("output pointer"));
+      }
+      let found = crypt_xchacha::validation_string(key.as_bytes(), ciphertext);
+      unsafe { put(out, into_c_string(found)?) };
+      Ok(())
+    })
+}
+
+/// The bytes of a buffer, valid until it is freed.
+///
+/// # Safety
+/// buffer must be a buffer handle from this library that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_buffer_data(buffer: *const EnchanterBuffer) -> *const u8 {
+    unsafe { buffer.as_ref() }.map_or(ptr::null(), |buffer| buffer.0.as_ptr())
+}
+
+/// The length of a buffer.
+///
+/// # Safety
+/// buffer must be a buffer handle from this library that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_buffer_len(buffer: *const EnchanterBuffer) -> usize {
+    unsafe { buffer.as_ref() }.map_or(0, |buffer| buffer.0.len())
+}
+
+/// Zeroize and free a buffer handle. A null pointer is ignored.
+///
+/// # Safety
+/// buffer must be null or a buffer handle from this library that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_buffer_free(buffer: *mut EnchanterBuffer) {
+    if !buffer.is_null() {
+      let mut buffer = unsafe { Box::from_raw(buffer) };
+      buffer.0.zeroize();
+    }
+}
+
+/// Free a string returned by the library. A null pointer is ignored.
+///
+/// # Safety
+/// s must be null or a string from this library that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_string_free(s: *mut c_char) {
+    if !s.is_null() {
+      let mut bytes = unsafe { CString::from_raw(s) }.into_bytes();
+      bytes.zeroize();
+    }
+}
+
+/// Zeroize memory owned by the caller, such as a password, in a way the compiler won't remove.
+///
+/// # Safety
+/// data must point to len writable bytes, or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn enchanter_zeroize(data: *mut u8, len: usize) {
+    if !data.is_null() {
+      unsafe { slice::from_raw_parts_mut(data, len) }.zeroize();
+    }
+}
+
+/// The stable name of an error code, such as "E_AUTH", as a static string.
+#[unsafe(no_mangle)]
+pub extern "C" fn enchanter_error_name(code: i32) -> *const c_char {
+    let name: &'static CStr = match code {
+      ENCHANTER_OK => c"OK",
+      ENCHANTER_E_USAGE => c"E_USAGE",
+      ENCHANTER_E_AUTH => c"E_AUTH",
+      ENCHANTER_E_MANIFEST_MISSING => c"E_MANIFEST_MISSING",
+      ENCHANTER_E_MANIFEST_INVALID => c"E_MANIFEST_INVALID",
+      ENCHANTER_E_KDF => c"E_KDF",
+      ENCHANTER_E_IO => c"E_IO",
+      ENCHANTER_E_TRUNCATED => c"E_TRUNCATED",
+      ENCHANTER_E_MALFORMED => c"E_MALFORMED",
+      ENCHANTER_E_CANCELLED => c"E_CANCELLED",
+      ENCHANTER_E_PANIC => c"E_PANIC",
+      _ => c"E_FAILED",
+    };
+    name.as_ptr()
+}
@@ -0,0 +1,270 @@
+//! JWE compact serialization with XChaCha20Poly1305, behind the `jwe` feature.
+//!
+//! A token is five base64url parts separated by dots: the protected header, the encrypted key, the
+//! 24 byte IV, the ciphertext and the 16 byte tag. The content is encrypted with `"enc": "XC20P"`
+//! (draft-amringer-jose-chacha), with the ASCII of the encoded protected header as associated data.
+//! The 32 byte content encryption key (CEK) comes from one of two key management algorithms:
+//!
+//! - `dir`: the CEK is a key used directly, such as an `a3` key, and the encrypted key is empty.
+//! - `PBES2-HS512+A256KW` (RFC 7518 section 4.8): a random CEK is wrapped with AES-256 key wrap,
+//!   under a key derived from a password with PBKDF2-HMAC-SHA512, its salt and count in the header.
+//!
+//! ```text
+//! eyJhbGciOiJkaXIiLCJlbmMiOiJYQzIwUCJ9..<iv>.<ciphertext>.<tag>
+//! ```
+
+use aes_kw::KekAes256;
+use base64::prelude::*;
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+#[allow(deprecated)]
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::XChaCha20Poly1305;
+use rand::rngs::OsRng;
+use rand_core::TryCryptoRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+use zeroize::Zeroize;
+
+use crate::error::Error;
+
+/// The content encryption of every token.
+pub const ENC: &str = "XC20P";
+/// The PBKDF2 iteration count of new PBES2 tokens, the OWASP recommendation for PBKDF2-HMAC-SHA512.
+pub const PBES2_COUNT: u32 = 210_000;
+/// Tokens asking for more PBKDF2 iterations than this are refused before any work is done.
+pub const MAX_PBES2_COUNT: u32 = 10_000_000;
+
+const PBES2_ALG: &str = "PBES2-HS512+A256KW";
+const PBES2_SALT_LEN: usize = 16;
+
+/// The key management algorithm of a token.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alg {
+    /// `dir`, the key is the CEK.
+    #[default]
+    Dir,
+    /// `PBES2-HS512+A256KW`, the CEK is wrapped under a password.
+    Pbes2,
+}
+
+impl Alg {
+    /// The `alg` header value.
+    pub fn name(self) -> &'static str {
+        match self {
+          Alg::Dir => "dir",
+          Alg::Pbes2 => PBES2_ALG,
+        }
+    }
+}
+
+impl core::str::FromStr for Alg {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Alg, Error> {
+        match s {
+          "dir" => Ok(Alg::Dir),
+          PBES2_ALG => Ok(Alg::Pbes2),
+          _ => Err(Error::Usage(format!("Unknown JWE alg {s}, use dir or {PBES2_ALG}"))),
+        }
+    }
+}
+
+/// The key a token is encrypted or decrypted with, which selects the algorithm.
+#[derive(Clone, Copy)]
+pub enum JweKey<'a> {
+    /// A 32 byte key for `dir`.
+    Direct(&'a [u8]),
+    /// A password for `PBES2-HS512+A256KW`.
+    Password(&'a [u8]),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    enc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    p2s: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    p2c: Option<u32>,
+    #[serde(default, skip_serializing)]
+    crit: Option<Vec<String>>,
+    #[serde(default, skip_serializing)]
+    zip: Option<String>,
+}
+
+/// A token split into its decoded parts.
+struct Parts<'a> {
+    header: Header,
+    encoded_header: &'a str,
+    encrypted_key: Vec<u8>,
+    iv: [u8; 24],
+    ciphertext: Vec<u8>,
+    tag: [u8; 16],
+}
+
+/// Whether data looks like a JWE compact token: a base64url JSON object header and five parts.
+pub fn is_jwe(data: &[u8]) -> bool {
+    let data = data.trim_ascii();
+    data.starts_with(b"eyJ") && data.iter().filter(|&&b| b == b'.').count() == 4
+}
+
+/// Encrypt plaintext into a compact token, with a CEK, IV and PBES2 salt from the OS RNG.
+pub fn encrypt(plaintext: &[u8], key: JweKey<'_>) -> Result<String, Error> {
+    encrypt_with_rng(plaintext, key, &mut OsRng)
+}
+
+/// The same as encrypt, with the random values from the caller's RNG.
+pub fn encrypt_with_rng<R: TryCryptoRng + ?Sized>(plaintext: &[u8], key: JweKey<'_>, rng: &mut R) -> Result<String, Error> {
+    let random = |rng: &mut R, bytes: &mut [u8]| rng.try_fill_bytes(bytes).map_err(|e| Error::Failed(format!("Failed to generate random bytes: {e}")));
+    let mut iv = [0u8; 24];
+    random(rng, &mut iv)?;
+    match key {
+      JweKey::Direct(key) => {
+        check_cek(key)?;
+        let header = Header { alg: Alg::Dir.name().into(), enc: ENC.into(), p2s: None, p2c: None, crit: None, zip: None };
+        seal(&header, &[], key, &iv, plaintext)
+      },
+      JweKey::Password(password) => {
+        let mut salt = [0u8; PBES2_SALT_LEN];
+        random(rng, &mut salt)?;
+        let mut cek = [0u8; 32];
+        random(rng, &mut cek)?;
+        let p2s = BASE64_URL_SAFE_NO_PAD.encode(salt);
+        let encrypted_key = wrap_pbes2(password, &p2s, PBES2_COUNT, &cek);
+        let header = Header { alg: Alg::Pbes2.name().into(), enc: ENC.into(), p2s: Some(p2s), p2c: Some(PBES2_COUNT), crit: None, zip: None };
+        let token = encrypted_key.and_then(|encrypted_key| seal(&header, &encrypted_key, &cek, &iv, plaintext));
+        cek.zeroize();
+        token
+      },
+    }
+}
+
+/// The key management algorithm of a token, read from its protected header.
+pub fn alg(token: &str) -> Result<Alg, Error> {
+    let parts = parse(token)?;
+    parts.header.alg.parse().map_err(|_| Error::Malformed(format!("Unsupported JWE alg {}", parts.header.alg)))
+}
+
+/// The CEK of a token: the key itself for `dir`, or the key unwrapped with the password for PBES2.
+/// A wrong password is an E_AUTH error, and a key of the wrong kind for the token's alg is E_USAGE.
+pub fn content_key(token: &str, key: JweKey<'_>) -> Result<[u8; 32], Error> {
+    let parts = parse(token)?;
+    match (parts.header.alg.as_str(), key) {
+      ("dir", JweKey::Direct(key)) => {
+        check_cek(key)?;
+        if !parts.encrypted_key.is_empty() {
+          return Err(Error::Malformed("The encrypted key of a dir token must be empty".into()));
+        }
+        let mut cek = [0u8; 32];
+        cek.copy_from_slice(key);
+        Ok(cek)
+      },
+      (PBES2_ALG, JweKey::Password(password)) => {
+        let malformed = || Error::Malformed("A PBES2 token needs p2s and p2c in its header".into());
+        let p2s = parts.header.p2s.as_deref().ok_or_else(malformed)?;
+        let p2c = parts.header.p2c.ok_or_else(malformed)?;
+        if !(1000..=MAX_PBES2_COUNT).contains(&p2c) {
+          return Err(Error::Malformed(format!("The PBES2 count {p2c} is outside 1000 to {MAX_PBES2_COUNT}")));
+        }
+        unwrap_pbes2(password, p2s, p2c, &parts.encrypted_key)
+      },
+      ("dir", JweKey::Password(_)) => Err(Error::Usage("A dir token is decrypted with a key, not a password".into())),
+      (PBES2_ALG, JweKey::Direct(_)) => Err(Error::Usage("A PBES2 token is decrypted with a password, not a key".into())),
+      (alg, _) => Err(Error::Malformed(format!("Unsupported JWE alg {alg}"))),
+    }
+}
+
+/// Decrypt a compact token. Nothing is returned unless the whole token authenticates.
+pub fn decrypt(token: &str, key: JweKey<'_>) -> Result<Vec<u8>, Error> {
+    let mut cek = content_key(token, key)?;
+    let plaintext = decrypt_with_content_key(token, &cek);
+    cek.zeroize();
+    plaintext
+}
+
+/// Decrypt a compact token with its CEK, whatever the key management algorithm.
+#[allow(deprecated)]
+pub fn decrypt_with_content_key(token: &str, cek: &[u8]) -> Result<Vec<u8>, Error> {
+    check_cek(cek)?;
+    let parts = parse(token)?;
+    let aead = XChaCha20Poly1305::new(GenericArray::from_slice(cek));
+    let mut plaintext = parts.ciphertext;
+    aead.decrypt_in_place_detached(GenericArray::from_slice(&parts.iv), parts.encoded_header.as_bytes(), &mut plaintext, GenericArray::from_slice(&parts.tag))
+        .map_err(|_| Error::Auth("Failed to authenticate the JWE".into()))?;
+    Ok(plaintext)
+}
+
+#[allow(deprecated)]
+fn seal(header: &Header, encrypted_key: &[u8], cek: &[u8], iv: &[u8; 24], plaintext: &[u8]) -> Result<String, Error> {
+    let header = serde_json::to_vec(header).map_err(|e| Error::Failed(format!("Failed to serialize the JWE header: {e}")))?;
+    let encoded_header = BASE64_URL_SAFE_NO_PAD.encode(header);
+    let aead = XChaCha20Poly1305::new(GenericArray::from_slice(cek));
+    let mut ciphertext = plaintext.to_vec();
+    let tag = aead.encrypt_in_place_detached(GenericArray::from_slice(iv), encoded_header.as_bytes(), &mut ciphertext)
+        .map_err(|_| Error::Failed("Failed to encrypt the plaintext".into()))?;
+    let encode = |part: &[u8]| BASE64_URL_SAFE_NO_PAD.encode(part);
+    Ok(format!("{encoded_header}.{}.{}.{}.{}", encode(encrypted_key), encode(iv), encode(&ciphertext), encode(&tag)))
+}
+
+fn parse(token: &str) -> Result<Parts<'_>, Error> {
+    let malformed = |what: &str| Error::Malformed(format!("Not a JWE compact token: {what}"));
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    let [encoded_header, encrypted_key, iv, ciphertext, tag] = parts[..] else {
+      return Err(malformed("it needs five parts separated by dots"));
+    };
+    let decode = |part: &str, what: &str| BASE64_URL_SAFE_NO_PAD.decode(part).map_err(|_| malformed(what));
+    let header: Header = serde_json::from_slice(&decode(encoded_header, "the header is not base64url")?)
+      .map_err(|_| malformed("the header is not a JOSE header"))?;
+    if header.enc != ENC {
+      return Err(Error::Malformed(format!("Unsupported JWE enc {}, only {ENC} is supported", header.enc)));
+    }
+    if header.crit.is_some() || header.zip.is_some() {
+      return Err(Error::Malformed("JWE crit and zip headers are not supported".into()));
+    }
+    Ok(Parts {
+      header,
+      encoded_header,
+      encrypted_key: decode(encrypted_key, "the encrypted key is not base64url")?,
+      iv: decode(iv, "the IV is not base64url")?.try_into().map_err(|_| malformed("the IV is not 24 bytes"))?,
+      ciphertext: decode(ciphertext, "the ciphertext is not base64url")?,
+      tag: decode(tag, "the tag is not base64url")?.try_into().map_err(|_| malformed("the tag is not 16 bytes"))?,
+    })
+}
+
+fn check_cek(key: &[u8]) -> Result<(), Error> {
+    if key.len() != 32 {
+      return Err(Error::Usage(format!("The XC20P key is {} bytes instead of 32", key.len())));
+    }
+    Ok(())
+}
+
+/// The PBES2 key encryption key: PBKDF2-HMAC-SHA512 of the password, salted with the alg, a zero
+/// byte and the decoded p2s.
+fn pbes2_kek(password: &[u8], p2s: &str, p2c: u32) -> Result<KekAes256, Error> {
+    let salt_input = BASE64_URL_SAFE_NO_PAD.decode(p2s).map_err(|_| Error::Malformed("The PBES2 p2s is not base64url".into()))?;
+    let mut salt = Vec::with_capacity(PBES2_ALG.len() + 1 + salt_input.len());
+    salt.extend_from_slice(PBES2_ALG.as_bytes());
+    salt.push(0);
+    salt.extend_from_slice(&salt_input);
+    let mut derived = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha512>(password, &salt, p2c, &mut derived);
+    let kek = KekAes256::from(derived);
+    derived.zeroize();
+    Ok(kek)
+}
+
+pub(crate) fn wrap_pbes2(password: &[u8], p2s: &str, p2c: u32, cek: &[u8; 32]) -> Result<[u8; 40], Error> {
+    let mut wrapped = [0u8; 40];
+    pbes2_kek(password, p2s, p2c)?.wrap(cek, &mut wrapped).map_err(|e| Error::Failed(format!("Failed to wrap the JWE key: {e}")))?;
+    Ok(wrapped)
+}
+
+fn unwrap_pbes2(password: &[u8], p2s: &str, p2c: u32, encrypted_key: &[u8]) -> Result<[u8; 32], Error> {
+    if encrypted_key.len() != 40 {
+      return Err(Error::Malformed("A PBES2 encrypted key is 40 bytes".into()));
+    }
+    let mut cek = [0u8; 32];
+    pbes2_kek(password, p2s, p2c)?.unwrap(encrypted_key, &mut cek)
+      .map_err(|_| Error::Auth("The password does not unwrap the JWE key".into()))?;
+    Ok(cek)
+}
@@ -1,15 +1,51 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "age")]
+pub mod age;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+#[cfg(feature = "cose")]
+pub mod cose;
 mod crypt_xchacha;
+#[cfg(feature = "enchantress")]
+pub mod enchantress;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "jwe")]
+pub mod jwe;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "std")]
+mod range;
+#[cfg(feature = "serde")]
+mod sealed;
+#[cfg(feature = "std")]
+mod stream;
+pub mod v1;
 
 pub use crypt_xchacha::*;
+pub use error::Error;
+#[cfg(feature = "std")]
+pub use range::{decrypt_range, RangeReader};
+#[cfg(feature = "serde")]
+pub use sealed::Sealed;
+#[cfg(feature = "std")]
+pub use stream::{DecryptReader, EncryptWriter};
 
 /// These unit tests validate a number of critical upstream behaviors, including
 /// encryption, decryption, date/time, and hashing capabilities.
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
+    #[cfg(feature = "manifest")]
     #[test]
     fn datetest() {
       use chrono::prelude::*;
-      assert_eq!(Utc::now().to_string().is_empty(), false);
+      assert!(!Utc::now().to_string().is_empty());
       let dt_nano = NaiveDate::from_ymd_opt(2014, 11, 28).unwrap().and_hms_nano_opt(12, 0, 9, 1).unwrap().and_local_timezone(Utc).unwrap();
       assert_eq!(format!("{:?}", dt_nano), "2014-11-28T12:00:09.000000001Z");
     }
@@ -68,22 +104,22 @@ mod tests {
       use crate::crypt_xchacha;
 
       let input = b"test-case12341234";
-      let mut key = crypt_xchacha::a3(input, &TUR);
+      let mut key = crypt_xchacha::a3(input, TUR);
       let input_file = "./Cargo.toml";
       let output_file = "./test.e1";
       let _ = crypt_xchacha::encrypt_file(input_file, output_file, &key);
-      let out_file = File::open(output_file).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to open the output file: {}", e)));
+      let out_file = File::open(output_file).map_err(|e| io::Error::other(format!("Failed to open the output file: {}", e)));
       let mut output_file_data = Vec::new();
-      let _ = out_file.expect("failed to read test file").read_to_end(&mut output_file_data).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to read {input_file}: {}", e)));
+      let _ = out_file.expect("failed to read test file").read_to_end(&mut output_file_data).map_err(|e| io::Error::other(format!("Failed to read {input_file}: {}", e)));
       let validate = crypt_xchacha::ciphertext_hash(&key, &output_file_data, 64);
       let validate_str = BASE64_STANDARD.encode(&validate);
       let _ = crypt_xchacha::encrypt_file(input_file, output_file, &key);
-      let out_file = File::open(output_file).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to open the output file: {}", e)));
+      let out_file = File::open(output_file).map_err(|e| io::Error::other(format!("Failed to open the output file: {}", e)));
       let mut output_file_data = Vec::new();
-      let _ = out_file.expect("failed to read test file").read_to_end(&mut output_file_data).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to read {input_file}: {}", e)));
+      let _ = out_file.expect("failed to read test file").read_to_end(&mut output_file_data).map_err(|e| io::Error::other(format!("Failed to read {input_file}: {}", e)));
       let validate2 = crypt_xchacha::ciphertext_hash(&key, &output_file_data, 64);
       let checkme = BASE64_STANDARD.encode(&validate2);
-      let _ = key.zeroize();
+      key.zeroize();
       assert_ne!(validate_str, checkme);
     }
 
@@ -96,13 +132,13 @@ mod tests {
       use crate::crypt_xchacha;
 
       let input = b"test-case12341234";
-      let key = crypt_xchacha::a3(input, &TUR);
+      let key = crypt_xchacha::a3(input, TUR);
       let input_file = "./Cargo.toml";
       let output_file = "./test.e2";
       let _ = crypt_xchacha::encrypt_file(input_file, output_file, &key);
-      let out_file = File::open("./test.e2").map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to open the output file: {}", e)));
+      let out_file = File::open("./test.e2").map_err(|e| io::Error::other(format!("Failed to open the output file: {}", e)));
       let mut output_file_data = Vec::new();
-      let _ = out_file.expect("failed to read test file").read_to_end(&mut output_file_data).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to read {input_file}: {}", e)));
+      let _ = out_file.expect("failed to read test file").read_to_end(&mut output_file_data).map_err(|e| io::Error::other(format!("Failed to read {input_file}: {}", e)));
       let _ = crypt_xchacha::ciphertext_hash(&key, &output_file_data, 64);
       let mut nonce = [0u8; 16];
 
@@ -111,23 +147,610 @@ mod tests {
       let validate_file = File::open("./test.o2");
       let mut input_file_data = Vec::new();
       let _ = ciphertext_file.as_ref().expect("failed to read file").read_exact(&mut nonce);
-      let _ = ciphertext_file.expect("failed to read file").read_to_end(&mut input_file_data).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to read {input_file}: {}", e)));
+      let _ = ciphertext_file.expect("failed to read file").read_to_end(&mut input_file_data).map_err(|e| io::Error::other(format!("Failed to read {input_file}: {}", e)));
       let validate = crypt_xchacha::ciphertext_hash(&key, &input_file_data, 64);
       let validate_str = BASE64_STANDARD.encode(&validate);
       let checkme = &validate_str;
-      if crypt_xchacha::checks(checkme, &validate_str) == true {
-        let _ = crypt_xchacha::decrypt_file("./test.e2", "./test.o2", &key).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Decryption failed: {}", e)));
+      if crypt_xchacha::checks(checkme, &validate_str) {
+        let _ = crypt_xchacha::decrypt_file("./test.e2", "./test.o2", &key).map_err(|e| io::Error::other(format!("Decryption failed: {}", e)));
         println!("{{\"Result\": \"file decrypted\"}}");
       } else {
         println!("  \"Result\": \"Refusing to decrypt.\"\n}}");
       };
       let mut out_file_data = Vec::new();
-      let _ = validate_file.expect("failed to read file").read_to_end(&mut out_file_data).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to read test plaintext: {}", e)));
+      let _ = validate_file.expect("failed to read file").read_to_end(&mut out_file_data).map_err(|e| io::Error::other(format!("Failed to read test plaintext: {}", e)));
       let mut og_file_data = Vec::new();
       let input_file_o = File::open(input_file);
-      let _ = input_file_o.expect("failed to read source file").read_to_end(&mut og_file_data).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to read test source plaintext: {}", e)));
+      let _ = input_file_o.expect("failed to read source file").read_to_end(&mut og_file_data).map_err(|e| io::Error::other(format!("Failed to read test source plaintext: {}", e)));
 
       assert_eq!(out_file_data, og_file_data);
     }
 
+    #[test]
+    fn errortest() {
+      use std::fs;
+      use crate::crypt_xchacha::TUR;
+      use crate::crypt_xchacha;
+
+      let key = crypt_xchacha::a3(b"test-case12341234", TUR);
+      let wrong = crypt_xchacha::a3(b"test-case43214321", TUR);
+      let _ = crypt_xchacha::encrypt_file("./Cargo.toml", "./test.e3", &key);
+      let error = crypt_xchacha::decrypt_file("./test.e3", "./test.o3", &wrong).unwrap_err();
+      assert_eq!((error.code(), error.exit_code()), ("E_AUTH", 3));
+      let _ = fs::write("./test.e3", b"too short");
+      let error = crypt_xchacha::decrypt_file("./test.e3", "./test.o3", &key).unwrap_err();
+      assert_eq!((error.code(), error.exit_code()), ("E_TRUNCATED", 8));
+      let error = crypt_xchacha::try_a3(b"test-case12341234", b"short").unwrap_err();
+      assert_eq!(error.code(), "E_KDF");
+      assert_eq!(crypt_xchacha::validate("abc", "abd").unwrap_err().code(), "E_AUTH");
+    }
+
+    #[test]
+    fn singlepasstest() {
+      use crate::crypt_xchacha::TUR;
+      use crate::crypt_xchacha;
+
+      let key = crypt_xchacha::a3(b"test-case12341234", TUR);
+      let validate_str = crypt_xchacha::encrypt_file_validated("./Cargo.toml", "./test.e4", &key).expect("encryption failed");
+      let data = crypt_xchacha::read_ciphertext("./test.e4").expect("failed to read test file");
+      assert_eq!(crypt_xchacha::validation_string(&key, &data), validate_str);
+      let checkme = crypt_xchacha::decrypt_file_validated("./test.e4", "./test.o4", &key, Some(&validate_str), 1).expect("decryption failed");
+      assert_eq!(checkme, validate_str);
+      assert_eq!(std::fs::read("./test.o4").unwrap(), std::fs::read("./Cargo.toml").unwrap());
+      let error = crypt_xchacha::decrypt_validated(&data, &key, Some("not the hash"), 1).err().expect("validation passed");
+      assert_eq!(error.code(), "E_AUTH");
+    }
+
+    #[test]
+    fn chunkedtest() {
+      use crate::crypt_xchacha::{self, CipherSuite};
+
+      let key = [3u8; 32];
+      let prefix = [9u8; 19];
+      let plaintext: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+      let single = crypt_xchacha::seal_chunked_with_prefix(&plaintext, &key, 1000, 1, CipherSuite::XChaCha20Poly1305, &prefix).unwrap();
+      let parallel = crypt_xchacha::seal_chunked_with_prefix(&plaintext, &key, 1000, 4, CipherSuite::XChaCha20Poly1305, &prefix).unwrap();
+      assert_eq!(single, parallel);
+      // More threads than chunks, and every setting shares the global pool.
+      for threads in [0, 3, 64] {
+        assert_eq!(crypt_xchacha::seal_chunked_with_prefix(&plaintext, &key, 1000, threads, CipherSuite::XChaCha20Poly1305, &prefix).unwrap(), single);
+      }
+      assert_eq!(single.len(), crypt_xchacha::CHUNKED_HEADER_LEN + plaintext.len() + 10 * 16);
+      assert_eq!(crypt_xchacha::open_any(&single, &key, 4).unwrap(), plaintext);
+      assert_eq!(crypt_xchacha::open_chunked(&single[..single.len() - 1016], &key, 1).unwrap_err().code(), "E_AUTH");
+      let empty = crypt_xchacha::seal_chunked(b"", &key, 1000, 0).unwrap();
+      assert_eq!(crypt_xchacha::open_any(&empty, &key, 1).unwrap(), b"");
+    }
+
+    #[test]
+    fn suitetest() {
+      use std::io::{Read, Write};
+      use crate::crypt_xchacha::{self, CipherSuite, Format};
+      use crate::{range, stream};
+
+      let key = [4u8; 32];
+      let prefix = [8u8; 19];
+      let plaintext: Vec<u8> = (0..5000u32).map(|i| (i * 13) as u8).collect();
+      let suite = CipherSuite::Aes256GcmSiv;
+      let single = crypt_xchacha::seal_chunked_with_prefix(&plaintext, &key, 1000, 1, suite, &prefix).unwrap();
+      let parallel = crypt_xchacha::seal_chunked_with_prefix(&plaintext, &key, 1000, 4, suite, &prefix).unwrap();
+      assert_eq!(single, parallel);
+      assert_eq!(single[8], 1);
+      assert_eq!(crypt_xchacha::parse_chunked_header(&single).unwrap().suite, suite);
+      assert_ne!(single, crypt_xchacha::seal_chunked_with_prefix(&plaintext, &key, 1000, 1, CipherSuite::XChaCha20Poly1305, &prefix).unwrap());
+      assert_eq!(crypt_xchacha::open_any(&single, &key, 4).unwrap(), plaintext);
+      assert_eq!(crypt_xchacha::open_any(&single, &[5u8; 32], 1).unwrap_err().code(), "E_AUTH");
+
+      let mut writer = stream::EncryptWriter::with_suite(Vec::new(), &key, Format::chunked(700).threads(1), suite).unwrap();
+      writer.write_all(&plaintext).unwrap();
+      let (streamed, _) = writer.finish().unwrap();
+      let mut decrypted = Vec::new();
+      stream::DecryptReader::new(&streamed[..], &key).unwrap().read_to_end(&mut decrypted).unwrap();
+      assert_eq!(decrypted, plaintext);
+      let mut reader = range::RangeReader::new(std::io::Cursor::new(&streamed), &key).unwrap();
+      let mut buffer = [0u8; 100];
+      std::io::Seek::seek(&mut reader, std::io::SeekFrom::Start(690)).unwrap();
+      reader.read_exact(&mut buffer).unwrap();
+      assert_eq!(&buffer[..], &plaintext[690..790]);
+
+      let mut changed = single.clone();
+      changed[8] = 0;
+      assert_eq!(crypt_xchacha::open_any(&changed, &key, 1).unwrap_err().code(), "E_AUTH");
+      changed[8] = 2;
+      assert_eq!(crypt_xchacha::open_any(&changed, &key, 1).unwrap_err().code(), "E_MALFORMED");
+      assert_eq!(crypt_xchacha::seal_format_suite(b"data", &key, Format::Legacy, suite).unwrap_err().code(), "E_USAGE");
+      assert_eq!("aes-256-gcm-siv".parse::<CipherSuite>().unwrap(), suite);
+      assert_eq!("aes".parse::<CipherSuite>().unwrap_err().code(), "E_USAGE");
+    }
+
+    #[test]
+    fn rangetest() {
+      use std::fs;
+      use std::io::{Read, Seek, SeekFrom};
+      use crate::crypt_xchacha::Format;
+      use crate::{crypt_xchacha, range};
+
+      let key = [5u8; 32];
+      let plaintext: Vec<u8> = (0..5000u32).map(|i| (i * 7) as u8).collect();
+      let _ = fs::write("./test.o5", &plaintext);
+      let _ = crypt_xchacha::encrypt_file_format("./test.o5", "./test.e5", &key, Format::chunked(64).threads(1));
+      assert_eq!(range::decrypt_range("./test.e5", 100, 300, &key).unwrap(), &plaintext[100..400]);
+      assert_eq!(range::decrypt_range("./test.e5", 4990, 300, &key).unwrap(), &plaintext[4990..]);
+      assert_eq!(range::decrypt_range("./test.e5", 9000, 10, &key).unwrap(), b"");
+      assert_eq!(range::decrypt_range("./test.e5", 9000, 10, &[6u8; 32]).unwrap_err().code(), "E_AUTH");
+      assert_eq!(range::decrypt_range("./test.e5", 0, 10, &[6u8; 32]).unwrap_err().code(), "E_AUTH");
+
+      let mut reader = range::RangeReader::new(fs::File::open("./test.e5").unwrap(), &key).unwrap();
+      let mut buffer = [0u8; 100];
+      reader.seek(SeekFrom::End(-150)).unwrap();
+      reader.read_exact(&mut buffer).unwrap();
+      assert_eq!(&buffer[..], &plaintext[4850..4950]);
+      let mut rest = Vec::new();
+      reader.seek(SeekFrom::Start(10)).unwrap();
+      reader.read_to_end(&mut rest).unwrap();
+      assert_eq!(rest, &plaintext[10..]);
+
+      let _ = crypt_xchacha::encrypt_file("./test.o5", "./test.e5", &key);
+      assert_eq!(range::decrypt_range("./test.e5", 1000, 24, &key).unwrap(), &plaintext[1000..1024]);
+    }
+
+    #[test]
+    fn chunkedfiletest() {
+      use std::fs;
+      use crate::crypt_xchacha::{self, Format};
+
+      let key = [7u8; 32];
+      let plaintext: Vec<u8> = (0..5000u32).map(|i| (i * 11) as u8).collect();
+      let _ = fs::write("./test.o8", &plaintext);
+      let validate_str = crypt_xchacha::encrypt_file_format("./test.o8", "./test.e8", &key, Format::chunked(64).threads(1)).unwrap();
+      assert_eq!(crypt_xchacha::file_validation_string("./test.e8", &key).unwrap(), validate_str);
+      assert!(crypt_xchacha::is_chunked_file("./test.e8").unwrap());
+      for threads in [1, 3, 0] {
+        let _ = fs::remove_file("./test.d8");
+        assert_eq!(crypt_xchacha::decrypt_file_validated("./test.e8", "./test.d8", &key, Some(&validate_str), threads).unwrap(), validate_str);
+        assert_eq!(fs::read("./test.d8").unwrap(), plaintext);
+        let mut streamed = Vec::new();
+        assert_eq!(crypt_xchacha::decrypt_chunked_file("./test.e8", &mut streamed, &key, None, threads).unwrap(), validate_str);
+        assert_eq!(streamed, plaintext);
+      }
+
+      // A mismatch writes nothing, and a chunk that fails to authenticate removes the output file.
+      let _ = fs::remove_file("./test.d8");
+      let error = crypt_xchacha::decrypt_file_validated("./test.e8", "./test.d8", &key, Some("not the hash"), 2).unwrap_err();
+      assert_eq!(error.code(), "E_AUTH");
+      assert!(!fs::exists("./test.d8").unwrap());
+      let mut data = fs::read("./test.e8").unwrap();
+      data[4000] ^= 1;
+      let _ = fs::write("./test.e8", &data);
+      assert_eq!(crypt_xchacha::decrypt_file_validated("./test.e8", "./test.d8", &key, None, 2).unwrap_err().code(), "E_AUTH");
+      assert!(!fs::exists("./test.d8").unwrap());
+
+      // Sizes are checked against the header before the file is read, so these sparse files
+      // are refused without allocating for them.
+      let file = fs::File::create("./test.e8").unwrap();
+      file.set_len(crypt_xchacha::MAX_LEN + 1).unwrap();
+      assert_eq!(crypt_xchacha::read_ciphertext("./test.e8").unwrap_err().code(), "E_MALFORMED");
+      let mut header = data[..crypt_xchacha::CHUNKED_HEADER_LEN].to_vec();
+      header.resize(crypt_xchacha::CHUNKED_HEADER_LEN + 80 + 8, 0);
+      let _ = fs::write("./test.e8", &header);
+      assert_eq!(crypt_xchacha::read_ciphertext("./test.e8").unwrap_err().code(), "E_MALFORMED");
+      assert_eq!(crypt_xchacha::is_chunked_file("./test.e8").unwrap_err().code(), "E_MALFORMED");
+      fs::File::options().write(true).open("./test.e8").unwrap().set_len(crypt_xchacha::CHUNKED_HEADER_LEN as u64 + 80 * (1 << 32) + 8).unwrap();
+      assert_eq!(crypt_xchacha::read_ciphertext("./test.e8").unwrap_err().code(), "E_MALFORMED");
+      let _ = fs::remove_file("./test.e8");
+    }
+
+    #[test]
+    fn streamtest() {
+      use std::io::{Read, Write};
+      use crate::crypt_xchacha::{self, Format};
+      use crate::stream::{DecryptReader, EncryptWriter};
+
+      let key = [9u8; 32];
+      let plaintext: Vec<u8> = (0..1000u32).map(|i| (i * 13) as u8).collect();
+      for format in [Format::Legacy, Format::chunked(100).threads(1), Format::chunked(64).threads(1)] {
+        let mut writer = EncryptWriter::new(Vec::new(), &key, format).unwrap();
+        for piece in plaintext.chunks(37) {
+          writer.write_all(piece).unwrap();
+        }
+        let (ciphertext, validation) = writer.finish().unwrap();
+        assert_eq!(validation, crypt_xchacha::validation_string(&key, &ciphertext));
+        assert_eq!(crypt_xchacha::open_any(&ciphertext, &key, 1).unwrap(), plaintext);
+
+        let mut reader = DecryptReader::new(&ciphertext[..], &key).unwrap();
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, plaintext);
+        assert_eq!(reader.validation_string(), Some(validation.as_str()));
+
+        let mut reader = DecryptReader::new(&ciphertext[..ciphertext.len() - 120], &key).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+      }
+
+      let sealed = crypt_xchacha::seal_chunked(b"", &key, 16, 1).unwrap();
+      let mut reader = DecryptReader::new(&sealed[..], &key).unwrap();
+      assert_eq!(reader.read_to_end(&mut Vec::new()).unwrap(), 0);
+    }
+
+    #[test]
+    fn bytestest() {
+      use crate::crypt_xchacha::{self, Key, TUR};
+
+      let key = Key::from_password(b"test-case12341234", TUR).unwrap();
+      let encrypted = crypt_xchacha::encrypt_bytes(b"a token", &key).unwrap();
+      assert_eq!(encrypted.validation_string, crypt_xchacha::validation_string(key.as_bytes(), &encrypted.ciphertext));
+      assert_eq!(crypt_xchacha::open(&encrypted.ciphertext, key.as_bytes()).unwrap(), b"a token");
+      let decrypted = crypt_xchacha::decrypt_bytes(&encrypted.ciphertext, &key).unwrap();
+      assert_eq!(decrypted.plaintext, b"a token");
+      assert_eq!(decrypted.validation_string, encrypted.validation_string);
+      assert_eq!(crypt_xchacha::decrypt_bytes(&encrypted.ciphertext, &Key::new([1u8; 32])).err().map(|e| e.code()), Some("E_AUTH"));
+      assert_eq!(Key::try_from(&[0u8; 31][..]).unwrap_err().code(), "E_KDF");
+    }
+
+    #[test]
+    fn rngtest() {
+      use rand::SeedableRng;
+      use rand::rngs::StdRng;
+      use crate::crypt_xchacha::{self, Key};
+
+      let key = Key::new([3u8; 32]);
+      let mut rng = StdRng::seed_from_u64(42);
+      let encrypted = crypt_xchacha::encrypt_bytes_with_rng(b"config", &key, &mut rng).unwrap();
+      assert_eq!(crypt_xchacha::decrypt_bytes(&encrypted.ciphertext, &key).unwrap().plaintext, b"config");
+      let sealed = crypt_xchacha::seal_chunked_with_rng(b"config", key.as_bytes(), 4, 1, &mut rng).unwrap();
+      assert_eq!(crypt_xchacha::open_any(&sealed, key.as_bytes(), 1).unwrap(), b"config");
+      assert_ne!(&sealed[13..32], &encrypted.ciphertext[..19]);
+    }
+
+    #[cfg(feature = "manifest")]
+    #[test]
+    fn v1test() {
+      use crate::v1::{self, Format, Manifest};
+
+      let key = v1::derive_key(b"test-case12341234").unwrap();
+      let _ = std::fs::write("./test.o7", b"stable");
+      let validation = v1::encrypt_file("./test.o7", "./test.e7", &key, Format::Legacy).unwrap();
+      Manifest::new("./test.e7", &validation).write("./test.m7").unwrap();
+      let manifest = Manifest::read("./test.m7").unwrap();
+      assert_eq!(manifest.ciphertext_path.as_deref(), Some("./test.e7"));
+      assert_eq!(v1::decrypt_file("./test.e7", "./test.o7", &key, Some(&manifest.ciphertext_hash)).unwrap(), validation);
+      assert_eq!(std::fs::read("./test.o7").unwrap(), b"stable");
+      assert_eq!(Manifest::read("./test.missing").unwrap_err().code(), "E_MANIFEST_MISSING");
+      assert!(matches!(Format::chunked(64).threads(2), Format::Chunked { chunk_size: 64, threads: 2, .. }));
+      assert_eq!(Format::Legacy.threads(2), Format::Legacy);
+    }
+
+    /// The samples in testdata/enchantress were written by enchantress 0.1.12 with `-ee` and `-gee`.
+    #[cfg(feature = "enchantress")]
+    #[test]
+    fn enchantresstest() {
+      use crate::crypt_xchacha::{self, CipherSuite, Format};
+      use crate::enchantress::{self, Manifest, Mode};
+
+      let plaintext = std::fs::read("testdata/enchantress/sample.txt").unwrap();
+      let key = enchantress::derive_key(b"test-case12341234").unwrap();
+      for (sample, manifest, mode) in [("sample.ctr.e", "ctr.toml", Mode::Ctr), ("sample.gcm.e", "gcm.toml", Mode::Gcm)] {
+        let data = std::fs::read(format!("testdata/enchantress/{sample}")).unwrap();
+        let manifest = Manifest::read(&format!("testdata/enchantress/{manifest}")).unwrap();
+        assert_eq!(manifest.mode().unwrap(), mode);
+        let decrypted = enchantress::decrypt_validated(&data, &key, &manifest.ciphertext_hash, mode).unwrap();
+        assert_eq!(decrypted.plaintext, plaintext);
+        let wrong = enchantress::derive_key(b"test-case12341235").unwrap();
+        assert_eq!(enchantress::decrypt_validated(&data, &wrong, &manifest.ciphertext_hash, mode).err().unwrap().code(), "E_AUTH");
+
+        let to_key = crypt_xchacha::a3(b"test-case12341234", crypt_xchacha::TUR);
+        let imported = enchantress::import(&data, &manifest, &key, &to_key, Format::Legacy, CipherSuite::XChaCha20Poly1305).unwrap();
+        assert_eq!(crypt_xchacha::decrypt_validated(&imported.ciphertext, &to_key, Some(&imported.validation_string), 1).unwrap().plaintext, plaintext);
+      }
+      assert_eq!(enchantress::decrypt_validated(&[0u8; 8], &key, "", Mode::Ctr).err().unwrap().code(), "E_TRUNCATED");
+    }
+
+    /// The vectors in testdata/age are from the age testkit (C2SP CCTV), as shipped with the age crate:
+    /// a few header lines, an empty line, then the age file. The payload is the SHA-256 of the plaintext.
+    #[cfg(feature = "age")]
+    #[test]
+    fn agetest() {
+      use sha2::{Digest, Sha256};
+      use crate::age::{self, Identities, Recipients};
+      use crate::crypt_xchacha::{self, CipherSuite, Format};
+
+      let key = [5u8; 32];
+      for vector in ["scrypt", "x25519", "x25519_multiple_recipients", "armor", "stream_two_chunks", "scrypt_bad_tag", "x25519_no_match", "stream_bad_tag"] {
+        let contents = std::fs::read(format!("testdata/age/{vector}")).unwrap();
+        let split = contents.windows(2).position(|w| w == b"\n\n").unwrap();
+        let (headers, data) = (std::str::from_utf8(&contents[..split]).unwrap(), &contents[split + 2..]);
+        let header = |name: &str| headers.lines().find_map(|line| line.strip_prefix(name)).map(str::to_string);
+        let identities = match header("identity: ") {
+          Some(identity) => Identities::X25519(vec![identity]),
+          None => Identities::Passphrase(header("passphrase: ").unwrap()),
+        };
+        assert!(age::is_age(data), "{vector}");
+        let imported = age::import(data, &identities, &key, Format::chunked(64).threads(1), CipherSuite::XChaCha20Poly1305);
+        match header("expect: ").unwrap().as_str() {
+          "success" => {
+            let imported = imported.unwrap();
+            let plaintext = crypt_xchacha::decrypt_validated(&imported.ciphertext, &key, Some(&imported.validation_string), 1).unwrap();
+            assert_eq!(format!("{:x}", Sha256::digest(&plaintext.plaintext)), header("payload: ").unwrap(), "{vector}");
+
+            let recipients = match &identities {
+              Identities::X25519(keys) => Recipients::X25519(vec![keys[0].parse::<::age::x25519::Identity>().unwrap().to_public().to_string()]),
+              Identities::Passphrase(passphrase) => Recipients::Passphrase(passphrase.clone()),
+            };
+            let exported = age::export(&imported.ciphertext, &key, Some(&imported.validation_string), &recipients, vector == "armor").unwrap();
+            assert!(age::is_age(&exported));
+            assert_eq!(age::decrypt(&exported, &identities).unwrap(), plaintext.plaintext, "{vector}");
+          },
+          _ => assert_eq!(imported.err().unwrap().code(), "E_AUTH", "{vector}"),
+        }
+      }
+      let sealed = crypt_xchacha::seal(b"not age", &key).unwrap();
+      assert!(!age::is_age(&sealed));
+      let recipients = Recipients::X25519(vec!["age1nope".into()]);
+      assert_eq!(age::export(&sealed, &key, None, &recipients, false).unwrap_err().code(), "E_USAGE");
+      assert_eq!(age::export(&sealed, &[6u8; 32], None, &recipients, false).unwrap_err().code(), "E_AUTH");
+    }
+
+    #[cfg(feature = "jwe")]
+    #[test]
+    fn jwetest() {
+      use base64::prelude::*;
+      use crate::jwe::{self, Alg, JweKey};
+
+      // RFC 7520 section 5.3, the PBES2-HS512+A256KW key wrap of the example CEK.
+      let password = "entrap_o\u{2013}peter_long\u{2013}credit_tun".as_bytes();
+      let cek: [u8; 32] = BASE64_URL_SAFE_NO_PAD.decode("uwsjJXaBK407Qaf0_zpcpmr1Cs0CC50hIUEyGNEt3m0").unwrap().try_into().unwrap();
+      let wrapped = jwe::wrap_pbes2(password, "8Q1SzinasR3xchYz6ZZcHA", 8192, &cek).unwrap();
+      assert_eq!(BASE64_URL_SAFE_NO_PAD.encode(wrapped), "d3qNhUWfqheyPp4H8sjOWsDYajoej4c5Je6rlUtFPWdgtURtmeDV1g");
+
+      let key = [4u8; 32];
+      let token = jwe::encrypt(b"payload", JweKey::Direct(&key)).unwrap();
+      assert!(token.starts_with("eyJhbGciOiJkaXIiLCJlbmMiOiJYQzIwUCJ9.."));
+      assert!(jwe::is_jwe(token.as_bytes()));
+      assert_eq!(jwe::alg(&token).unwrap(), Alg::Dir);
+      assert_eq!(jwe::decrypt(&token, JweKey::Direct(&key)).unwrap(), b"payload");
+      assert_eq!(jwe::decrypt(&token, JweKey::Direct(&[5u8; 32])).unwrap_err().code(), "E_AUTH");
+      assert_eq!(jwe::decrypt(&token, JweKey::Password(b"password")).unwrap_err().code(), "E_USAGE");
+
+      let token = jwe::encrypt(b"payload", JweKey::Password(password)).unwrap();
+      assert_eq!(jwe::alg(&token).unwrap(), Alg::Pbes2);
+      let cek = jwe::content_key(&token, JweKey::Password(password)).unwrap();
+      assert_eq!(jwe::decrypt_with_content_key(&token, &cek).unwrap(), b"payload");
+      assert_eq!(jwe::content_key(&token, JweKey::Password(b"wrong")).unwrap_err().code(), "E_AUTH");
+
+      // Changing the protected header breaks the tag.
+      let (header, rest) = token.split_once('.').unwrap();
+      let mut json = String::from_utf8(BASE64_URL_SAFE_NO_PAD.decode(header).unwrap()).unwrap();
+      json.insert_str(1, "\"kid\":\"x\",");
+      let tampered = format!("{}.{rest}", BASE64_URL_SAFE_NO_PAD.encode(json));
+      assert_eq!(jwe::decrypt_with_content_key(&tampered, &cek).unwrap_err().code(), "E_AUTH");
+      assert_eq!(jwe::decrypt("eyJ.a.b", JweKey::Direct(&key)).unwrap_err().code(), "E_MALFORMED");
+      assert!(!jwe::is_jwe(&crate::crypt_xchacha::seal(b"payload", &key).unwrap()));
+    }
+
+    #[cfg(feature = "cose")]
+    #[test]
+    fn cosetest() {
+      use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+      use chacha20poly1305::XChaCha20Poly1305;
+      use coset::TaggedCborSerializable;
+      use crate::cose::{self, Kdf};
+      use crate::crypt_xchacha::{self, CipherSuite, Format, TUR};
+
+      let key = [7u8; 32];
+      let kdf = Kdf { salt: TUR.to_vec() };
+      let message = cose::encrypt(b"firmware config", &key, Some(&kdf)).unwrap();
+      assert!(cose::is_cose(&message));
+      assert_eq!(cose::kdf(&message).unwrap(), Some(kdf.clone()));
+      assert_eq!(cose::decrypt(&message, &key).unwrap(), b"firmware config");
+      assert_eq!(cose::decrypt(&message, &[8u8; 32]).unwrap_err().code(), "E_AUTH");
+
+      // Decrypt as any COSE library would, with the Enc_structure ["Encrypt0", protected, h''].
+      let parsed = coset::CoseEncrypt0::from_tagged_slice(&message).unwrap();
+      let protected = parsed.protected.original_data.clone().unwrap();
+      let mut aad = vec![0x83, 0x68];
+      aad.extend_from_slice(b"Encrypt0");
+      aad.extend_from_slice(&[0x58, protected.len() as u8]);
+      aad.extend_from_slice(&protected);
+      aad.push(0x40);
+      let aead = XChaCha20Poly1305::new(&key.into());
+      let payload = Payload { msg: parsed.ciphertext.as_deref().unwrap(), aad: &aad };
+      assert_eq!(aead.decrypt(parsed.unprotected.iv.as_slice().into(), payload).unwrap(), b"firmware config");
+
+      // Native to COSE and back keeps the plaintext and the key.
+      let native = crypt_xchacha::seal(b"firmware config", &key).unwrap();
+      let expected = crypt_xchacha::validation_string(&key, &native);
+      let exported = cose::export(&native, &key, Some(&expected), None).unwrap();
+      assert_eq!(cose::kdf(&exported).unwrap(), None);
+      let imported = cose::import(&exported, &key, &key, Format::Legacy, CipherSuite::default()).unwrap();
+      assert_eq!(crypt_xchacha::open(&imported.ciphertext, &key).unwrap(), b"firmware config");
+      assert_eq!(cose::export(&native, &key, Some("wrong"), None).unwrap_err().code(), "E_AUTH");
+
+      let mut tampered = message.clone();
+      let at = tampered.windows(TUR.len()).position(|w| w == TUR).unwrap();
+      tampered[at] ^= 1;
+      assert_eq!(cose::decrypt(&tampered, &key).unwrap_err().code(), "E_AUTH");
+      assert_eq!(cose::decrypt(&native, &key).unwrap_err().code(), "E_MALFORMED");
+      assert!(!cose::is_cose(&native));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn sealedtest() {
+      use serde::{Deserialize, Serialize};
+      use crate::crypt_xchacha::Key;
+      use crate::sealed::Sealed;
+
+      #[derive(Serialize, Deserialize)]
+      struct Config {
+        user: String,
+        password: Sealed<String>,
+        ports: Sealed<Vec<u16>>,
+      }
+
+      let key = Key::new([9u8; 32]);
+      let config = Config {
+        user: "app".into(),
+        password: Sealed::seal(&"hunter2".to_string(), &key).unwrap(),
+        ports: Sealed::seal(&vec![80, 443], &key).unwrap(),
+      };
+      let json = serde_json::to_string(&config).unwrap();
+      assert!(!json.contains("hunter2"));
+      let config: Config = serde_json::from_str(&json).unwrap();
+      assert_eq!(config.password.unseal(&key).unwrap(), "hunter2");
+      assert_eq!(config.ports.unseal(&key).unwrap(), vec![80, 443]);
+      assert_eq!(config.password.unseal(&Key::new([1u8; 32])).unwrap_err().code(), "E_AUTH");
+      assert_eq!(config.password.to_string().parse::<Sealed<String>>().unwrap(), config.password);
+
+      let wrong_type: Sealed<u64> = config.password.to_string().parse().unwrap();
+      assert_eq!(wrong_type.unseal(&key).unwrap_err().code(), "E_MALFORMED");
+      assert!(serde_json::from_str::<Sealed<String>>("\"enchanter:bm9wZQ==:x\"").is_err());
+      assert!(serde_json::from_str::<Sealed<String>>("\"hunter2\"").is_err());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn asynctest() {
+      use tokio::io::{AsyncReadExt, AsyncWriteExt};
+      use tokio_util::sync::CancellationToken;
+      use crate::async_io;
+      use crate::crypt_xchacha::{self, Format, TUR};
+
+      let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+      runtime.block_on(async {
+        let key = async_io::a3(b"test-case12341234", TUR).await.unwrap();
+        assert_eq!(key.as_bytes(), &crypt_xchacha::a3(b"test-case12341234", TUR));
+        let key = key.as_bytes();
+
+        let plaintext: Vec<u8> = (0..1000u32).map(|i| (i * 11) as u8).collect();
+        let mut writer = async_io::EncryptWriter::new(Vec::new(), key, Format::chunked(100).threads(1)).unwrap();
+        writer.write_all(&plaintext).await.unwrap();
+        let (ciphertext, validation) = writer.finish().await.unwrap();
+        assert_eq!(validation, crypt_xchacha::validation_string(key, &ciphertext));
+        let mut reader = async_io::DecryptReader::new(&ciphertext[..], key).unwrap();
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).await.unwrap();
+        assert_eq!(decrypted, plaintext);
+        assert_eq!(reader.validation_string(), Some(validation.as_str()));
+
+        let cancel = CancellationToken::new();
+        let _ = std::fs::write("./test.o6", &plaintext);
+        let validation = async_io::encrypt_file("./test.o6", "./test.e6", key, Format::Legacy, &cancel).await.unwrap();
+        assert_eq!(async_io::decrypt_file("./test.e6", "./test.d6", key, Some(&validation), &cancel).await.unwrap(), validation);
+        assert_eq!(std::fs::read("./test.d6").unwrap(), plaintext);
+        let error = async_io::decrypt_file("./test.e6", "./test.d6", key, Some("wrong"), &cancel).await.unwrap_err();
+        assert_eq!(error.code(), "E_AUTH");
+        assert!(!std::path::Path::new("./test.d6").exists());
+
+        cancel.cancel();
+        let error = async_io::encrypt_file("./test.o6", "./test.e6", key, Format::Legacy, &cancel).await.unwrap_err();
+        assert_eq!(error.code(), "E_CANCELLED");
+        assert!(!std::path::Path::new("./test.e6").exists());
+      });
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn ffitest() {
+      use std::ffi::CStr;
+      use std::ptr;
+      use crate::ffi::*;
+
+      assert_eq!(std::fs::read_to_string("include/enchanter.h").unwrap(), include_str!(concat!(env!("OUT_DIR"), "/enchanter.h")),
+        "include/enchanter.h is out of date, copy it from the build directory");
+
+      unsafe {
+        let mut key = ptr::null_mut();
+        assert_eq!(enchanter_key_from_bytes([5u8; 32].as_ptr(), &mut key), ENCHANTER_OK);
+        let mut ciphertext = ptr::null_mut();
+        let mut validation = ptr::null_mut();
+        assert_eq!(enchanter_encrypt(key, b"c abi".as_ptr(), 5, &mut ciphertext, &mut validation), ENCHANTER_OK);
+        let data = std::slice::from_raw_parts(enchanter_buffer_data(ciphertext), enchanter_buffer_len(ciphertext));
+
+        let mut plaintext = ptr::null_mut();
+        assert_eq!(enchanter_decrypt(key, data.as_ptr(), data.len(), validation, &mut plaintext), ENCHANTER_OK);
+        assert_eq!(std::slice::from_raw_parts(enchanter_buffer_data(plaintext), enchanter_buffer_len(plaintext)), b"c abi");
+        assert_eq!(enchanter_decrypt(key, data.as_ptr(), data.len(), c"wrong".as_ptr(), &mut plaintext), ENCHANTER_E_AUTH);
+        assert_eq!(enchanter_decrypt(key, ptr::null(), 1, ptr::null(), &mut plaintext), ENCHANTER_E_USAGE);
+        assert_eq!(CStr::from_ptr(enchanter_error_name(ENCHANTER_E_AUTH)), c"E_AUTH");
+
+        enchanter_buffer_free(plaintext);
+        enchanter_buffer_free(ciphertext);
+        enchanter_string_free(validation);
+        enchanter_key_free(key);
+      }
+    }
+
+    mod malformed {
+      use proptest::prelude::*;
+      use crate::crypt_xchacha::{self, HEADER_LEN};
+
+      const KEY: [u8; 32] = [7u8; 32];
+
+      proptest! {
+        #[test]
+        fn parse_never_panics(data in proptest::collection::vec(any::<u8>(), 0..256)) {
+          match crypt_xchacha::parse(&data) {
+            Ok(envelope) => prop_assert_eq!(envelope.ciphertext.len() + HEADER_LEN, data.len()),
+            Err(e) => {
+              prop_assert!(data.len() < HEADER_LEN);
+              prop_assert_eq!(e.code(), "E_TRUNCATED");
+            },
+          }
+        }
+
+        #[test]
+        fn tampered_chunks_yield_no_plaintext(
+          plaintext in proptest::collection::vec(any::<u8>(), 0..512),
+          chunk_size in 1u32..100,
+          position in any::<prop::sample::Index>(),
+          flip in 1u8..=255,
+          cut in 1usize..64,
+        ) {
+          let sealed = crypt_xchacha::seal_chunked(&plaintext, &KEY, chunk_size, 1).unwrap();
+          prop_assert_eq!(crypt_xchacha::open_any(&sealed, &KEY, 2).unwrap(), plaintext);
+
+          let mut flipped = sealed.clone();
+          flipped[position.index(sealed.len())] ^= flip;
+          prop_assert!(crypt_xchacha::open_any(&flipped, &KEY, 1).is_err());
+
+          let truncated = &sealed[..sealed.len().saturating_sub(cut)];
+          prop_assert!(crypt_xchacha::open_any(truncated, &KEY, 1).is_err());
+        }
+
+        #[test]
+        fn arbitrary_input_yields_no_plaintext(data in proptest::collection::vec(any::<u8>(), 0..256)) {
+          let error = crypt_xchacha::open(&data, &KEY).unwrap_err();
+          prop_assert!(matches!(error.code(), "E_TRUNCATED" | "E_AUTH"));
+        }
+
+        #[test]
+        fn tampered_ciphertext_yields_no_plaintext(
+          plaintext in proptest::collection::vec(any::<u8>(), 0..128),
+          position in any::<prop::sample::Index>(),
+          flip in 1u8..=255,
+          cut in 0usize..64,
+          garbage in proptest::collection::vec(any::<u8>(), 1..32),
+        ) {
+          let sealed = crypt_xchacha::seal(&plaintext, &KEY).unwrap();
+          prop_assert_eq!(crypt_xchacha::open(&sealed, &KEY).unwrap(), plaintext);
+
+          let mut flipped = sealed.clone();
+          flipped[position.index(sealed.len())] ^= flip;
+          prop_assert!(crypt_xchacha::open(&flipped, &KEY).is_err());
+
+          let truncated = &sealed[..sealed.len().saturating_sub(cut + 1)];
+          prop_assert!(crypt_xchacha::open(truncated, &KEY).is_err());
+
+          let mut extended = sealed.clone();
+          extended.extend_from_slice(&garbage);
+          prop_assert_eq!(crypt_xchacha::open(&extended, &KEY).unwrap_err().code(), "E_AUTH");
+        }
+      }
+
+      #[test]
+      fn wrong_key_length_is_an_error() {
+        assert_eq!(crypt_xchacha::seal(b"data", &[0u8; 16]).unwrap_err().code(), "E_KDF");
+        assert_eq!(crypt_xchacha::open(&[0u8; 64], &[0u8; 31]).unwrap_err().code(), "E_KDF");
+      }
+    }
 }
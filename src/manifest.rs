@@ -0,0 +1,59 @@
+//! The enchanter.toml manifest, recorded with each encryption and required for decryption.
+//!
+//! ```toml
+//! ciphertext_path = "my_data.e"
+//! ciphertext_hash = "xshPOXhtqGJtBoIj/vvxWSh55hryEOMYRqOeedH0hJJccH/edQSUqXxkGvvaFNeJfL9NOaAVUdav4z1tAkn+/A=="
+//! creation_time = "2025-07-13 19:15:32.334352329 UTC"
+//! ```
+//!
+//! Only the ciphertext_hash is needed for decryption, the other fields are for people reading it.
+
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::io;
+
+use crate::error::Error;
+
+/// The name of the manifest, which the command line tool keeps in its working directory.
+pub const FILE_NAME: &str = "enchanter.toml";
+
+/// The contents of an enchanter.toml.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ciphertext_path: Option<String>,
+    /// The validation string of the ciphertext.
+    pub ciphertext_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creation_time: Option<String>,
+}
+
+impl Manifest {
+    /// A manifest for a ciphertext created now.
+    pub fn new(ciphertext_path: &str, ciphertext_hash: &str) -> Manifest {
+        let now: DateTime<Utc> = Utc::now();
+        Manifest {
+          ciphertext_path: Some(ciphertext_path.to_string()),
+          ciphertext_hash: ciphertext_hash.to_string(),
+          creation_time: Some(now.to_string()),
+        }
+    }
+
+    /// Read a manifest. A missing file is E_MANIFEST_MISSING and one that can't be parsed is
+    /// E_MANIFEST_INVALID.
+    pub fn read(path: &str) -> Result<Manifest, Error> {
+        let contents = fs::read_to_string(path).map_err(|e| match e.kind() {
+          io::ErrorKind::NotFound => Error::ManifestMissing(format!("No {path} found")),
+          _ => Error::Io(format!("Failed to read {path}: {e}")),
+        })?;
+        toml::from_str(&contents).map_err(|_| Error::ManifestInvalid(format!("Failed to parse {path}")))
+    }
+
+    /// Write the manifest, replacing any existing file.
+    pub fn write(&self, path: &str) -> Result<(), Error> {
+        let contents = toml::to_string(self).map_err(|e| Error::Failed(format!("Failed to serialize {path}: {e}")))?;
+        fs::write(path, contents).map_err(|e| Error::Io(format!("Failed to write {path}: {e}")))
+    }
+}
@@ -0,0 +1,153 @@
+//! Python bindings, behind the `python` feature. The `enchanter` module is built as a wheel with
+//! maturin from pyproject.toml, and its tests are in python/tests.
+//!
+//! Failures are raised as `enchanter.EnchanterError`, with the error code and the message as its
+//! args, such as `("E_AUTH", "...")`. Key derivation, encryption and decryption release the GIL, so
+//! they can run on several Python threads at once.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::crypt_xchacha::{self, CipherSuite, Format, Key, TUR};
+use crate::error::Error;
+use crate::manifest::Manifest;
+
+create_exception!(enchanter, EnchanterError, PyException, "A failed enchanter operation. args[0] is the error code, such as E_AUTH, and args[1] the message.");
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> PyErr {
+        EnchanterError::new_err((e.code(), e.to_string()))
+    }
+}
+
+/// A 32 byte key. Its bytes are zeroized when it is garbage collected, and never shown by repr.
+#[pyclass(name = "Key", module = "enchanter", frozen)]
+struct PyKey(Key);
+
+#[pymethods]
+impl PyKey {
+    #[new]
+    fn new(key: &[u8]) -> PyResult<PyKey> {
+        Ok(PyKey(Key::try_from(key)?))
+    }
+
+    fn __repr__(&self) -> &'static str {
+        "Key(..)"
+    }
+}
+
+/// The contents of an enchanter.toml.
+#[pyclass(name = "Manifest", module = "enchanter", frozen, get_all)]
+struct PyManifest {
+    ciphertext_path: Option<String>,
+    ciphertext_hash: String,
+    creation_time: Option<String>,
+}
+
+#[pymethods]
+impl PyManifest {
+    fn __repr__(&self) -> String {
+        format!("Manifest(ciphertext_path={:?}, ciphertext_hash={:?})", self.ciphertext_path, self.ciphertext_hash)
+    }
+}
+
+/// Derive a key from a password and salt with the three Argon2id rounds of a3.
+#[pyfunction]
+fn a3(py: Python<'_>, password: &[u8], salt: &[u8]) -> PyResult<PyKey> {
+    Ok(PyKey(py.detach(|| Key::from_password(password, salt))?))
+}
+
+/// Derive a key from a password the way the enchanter command does, so files can be shared with it.
+#[pyfunction]
+fn derive_key(py: Python<'_>, password: &[u8]) -> PyResult<PyKey> {
+    a3(py, password, TUR)
+}
+
+/// Encrypt bytes into the single buffer format, the same bytes as an encrypted file.
+/// Returns the ciphertext and its validation string.
+#[pyfunction]
+fn encrypt<'py>(py: Python<'py>, key: PyRef<'py, PyKey>, plaintext: &[u8]) -> PyResult<(Bound<'py, PyBytes>, String)> {
+    let key = &key.0;
+    let encrypted = py.detach(|| crypt_xchacha::encrypt_bytes(plaintext, key))?;
+    Ok((PyBytes::new(py, &encrypted.ciphertext), encrypted.validation_string))
+}
+
+/// Decrypt bytes in either format. With an expected validation string, a mismatch raises E_AUTH
+/// and nothing is decrypted.
+#[pyfunction]
+#[pyo3(signature = (key, ciphertext, expected = None))]
+fn decrypt<'py>(py: Python<'py>, key: PyRef<'py, PyKey>, ciphertext: &[u8], expected: Option<&str>) -> PyResult<Bound<'py, PyBytes>> {
+    let key = &key.0;
+    let decrypted = py.detach(|| crypt_xchacha::decrypt_validated(ciphertext, key.as_bytes(), expected, 0))?;
+    Ok(PyBytes::new(py, &decrypted.plaintext))
+}
+
+/// Encrypt a file and return the validation string of the output file. A chunk_size of 0 writes
+/// the single buffer format, anything else the chunked format with chunks of that size. The
+/// cipher is xchacha20poly1305, or aes-256-gcm-siv with a chunk_size.
+#[pyfunction]
+#[pyo3(signature = (key, input_file, output_file, chunk_size = 0, cipher = "xchacha20poly1305"))]
+fn encrypt_file(py: Python<'_>, key: PyRef<'_, PyKey>, input_file: &str, output_file: &str, chunk_size: u32, cipher: &str) -> PyResult<String> {
+    let key = &key.0;
+    let suite: CipherSuite = cipher.parse()?;
+    let format = match chunk_size {
+      0 => Format::Legacy,
+      chunk_size => Format::chunked(chunk_size),
+    };
+    Ok(py.detach(|| crypt_xchacha::encrypt_file_suite(input_file, output_file, key.as_bytes(), format, suite))?)
+}
+
+/// Decrypt a file in either format and return its validation string. With an expected
+/// validation string, a mismatch raises E_AUTH and the output file is not created.
+#[pyfunction]
+#[pyo3(signature = (key, input_file, output_file, expected = None))]
+fn decrypt_file(py: Python<'_>, key: PyRef<'_, PyKey>, input_file: &str, output_file: &str, expected: Option<&str>) -> PyResult<String> {
+    let key = &key.0;
+    Ok(py.detach(|| crypt_xchacha::decrypt_file_validated(input_file, output_file, key.as_bytes(), expected, 0))?)
+}
+
+/// The validation string of a ciphertext, the ciphertext_hash of its enchanter.toml.
+#[pyfunction]
+fn validation_string(py: Python<'_>, key: PyRef<'_, PyKey>, ciphertext: &[u8]) -> String {
+    let key = &key.0;
+    py.detach(|| crypt_xchacha::validation_string(key.as_bytes(), ciphertext))
+}
+
+/// Whether a ciphertext has the expected validation string, without decrypting it.
+#[pyfunction]
+fn verify(py: Python<'_>, key: PyRef<'_, PyKey>, ciphertext: &[u8], expected: &str) -> bool {
+    crypt_xchacha::validate(&validation_string(py, key, ciphertext), expected).is_ok()
+}
+
+/// Read an enchanter.toml. A missing file raises E_MANIFEST_MISSING and one that can't be parsed
+/// E_MANIFEST_INVALID.
+#[pyfunction]
+#[pyo3(signature = (path = "enchanter.toml"))]
+fn read_manifest(path: &str) -> PyResult<PyManifest> {
+    let manifest = Manifest::read(path)?;
+    Ok(PyManifest {
+      ciphertext_path: manifest.ciphertext_path,
+      ciphertext_hash: manifest.ciphertext_hash,
+      creation_time: manifest.creation_time,
+    })
+}
+
+#[pymodule]
+fn enchanter(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add("EnchanterError", m.py().get_type::<EnchanterError>())?;
+    m.add_class::<PyKey>()?;
+    m.add_class::<PyManifest>()?;
+    m.add_function(wrap_pyfunction!(a3, m)?)?;
+    m.add_function(wrap_pyfunction!(derive_key, m)?)?;
+    m.add_function(wrap_pyfunction!(encrypt, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt, m)?)?;
+    m.add_function(wrap_pyfunction!(encrypt_file, m)?)?;
+    m.add_function(wrap_pyfunction!(decrypt_file, m)?)?;
+    m.add_function(wrap_pyfunction!(validation_string, m)?)?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_function(wrap_pyfunction!(read_manifest, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,174 @@
+//! Random access to the plaintext of an encrypted file.
+//!
+//! In the chunked format each chunk is authenticated on its own, so a byte range is decrypted by
+//! reading, authenticating and decrypting only the chunks that cover it. Files in the single buffer
+//! format have one tag over the whole ciphertext, so they are decrypted in full when opened.
+//!
+//! Range reads do not compute the enchanter.toml validation string, which needs the whole file.
+//! A chunk that decrypts proves the key and that chunk, and the last chunk flag in the nonce means
+//! a file truncated at a chunk boundary fails when its final chunk is read.
+
+use zeroize::Zeroize;
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::crypt_xchacha::{self, ChunkCipher, CipherSuite, CHUNKED_HEADER_LEN, TAG_LEN};
+use crate::error::Error;
+
+/// A `Read + Seek` view of the plaintext of an encrypted file, decrypting one chunk at a time.
+/// Errors from reading are `io::Error`s wrapping an enchanter `Error`.
+pub struct RangeReader<R> {
+    inner: R,
+    cipher: ChunkCipher,
+    header: [u8; CHUNKED_HEADER_LEN],
+    chunk_size: u64,
+    chunks: u64,
+    ciphertext_len: u64,
+    plaintext_len: u64,
+    position: u64,
+    /// The index and plaintext of the last decrypted chunk.
+    cached: Option<(u64, Vec<u8>)>,
+    legacy: bool,
+}
+
+impl<R: Read + Seek> RangeReader<R> {
+    /// Open an encrypted file in either format. Chunked files are only checked for their structure
+    /// here, while single buffer files are decrypted and authenticated in full.
+    pub fn new(mut inner: R, key: &[u8]) -> Result<RangeReader<R>, Error> {
+        crypt_xchacha::check_key(key)?;
+        let ciphertext_len = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(0))?;
+        let mut header = [0u8; CHUNKED_HEADER_LEN];
+        let read = read_full(&mut inner, &mut header)?;
+
+        if !crypt_xchacha::is_chunked(&header[..read]) {
+          inner.seek(SeekFrom::Start(0))?;
+          if ciphertext_len > crypt_xchacha::MAX_LEN {
+            return Err(Error::Malformed(format!("The ciphertext is {ciphertext_len} bytes, larger than the maximum of {} bytes", crypt_xchacha::MAX_LEN)));
+          }
+          let mut data = Vec::with_capacity(ciphertext_len as usize);
+          inner.read_to_end(&mut data)?;
+          let plaintext = crypt_xchacha::open(&data, key)?;
+          let plaintext_len = plaintext.len() as u64;
+          return Ok(RangeReader {
+            inner, cipher: ChunkCipher::new(CipherSuite::XChaCha20Poly1305, key), header, chunk_size: plaintext_len.max(1), chunks: 1, ciphertext_len, plaintext_len,
+            position: 0, cached: Some((0, plaintext)), legacy: true,
+          });
+        }
+
+        let parsed = crypt_xchacha::parse_chunked_header(&header)?;
+        let chunk_size = parsed.chunk_size as u64;
+        let chunks = crypt_xchacha::chunk_count(&parsed, ciphertext_len)?;
+        let plaintext_len = ciphertext_len - CHUNKED_HEADER_LEN as u64 - chunks * TAG_LEN as u64;
+        Ok(RangeReader {
+          inner, cipher: ChunkCipher::new(parsed.suite, key), header, chunk_size, chunks, ciphertext_len, plaintext_len,
+          position: 0, cached: None, legacy: false,
+        })
+    }
+
+    /// The length of the plaintext.
+    pub fn plaintext_len(&self) -> u64 {
+        self.plaintext_len
+    }
+
+    /// Read, authenticate and decrypt one chunk into the cache.
+    fn load_chunk(&mut self, index: u64) -> Result<(), Error> {
+        if self.legacy || self.cached.as_ref().is_some_and(|(cached, _)| *cached == index) {
+          return Ok(());
+        }
+        let stride = self.chunk_size + TAG_LEN as u64;
+        let start = CHUNKED_HEADER_LEN as u64 + index * stride;
+        let len = stride.min(self.ciphertext_len - start) as usize;
+        let mut chunk = vec![0u8; len];
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.read_exact(&mut chunk).map_err(|e| Error::Io(format!("Failed to read chunk {index}: {e}")))?;
+        let (ciphertext, tag) = chunk.split_at_mut(len - TAG_LEN);
+        let prefix = self.header[13..].first_chunk::<19>().ok_or_else(|| Error::Malformed("Invalid nonce prefix".into()))?;
+        if let Err(e) = self.cipher.open(prefix, index as usize, index == self.chunks - 1, &self.header, ciphertext, tag) {
+          chunk.zeroize();
+          return Err(e);
+        }
+        chunk.truncate(len - TAG_LEN);
+        if let Some((_, mut old)) = self.cached.replace((index, chunk)) {
+          old.zeroize();
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for RangeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.plaintext_len || buf.is_empty() {
+          return Ok(0);
+        }
+        let index = if self.legacy { 0 } else { self.position / self.chunk_size };
+        self.load_chunk(index).map_err(io::Error::other)?;
+        let offset = (self.position - index * self.chunk_size) as usize;
+        let plaintext = self.cached.as_ref().map(|(_, plaintext)| &plaintext[offset..]).unwrap_or_default();
+        let n = plaintext.len().min(buf.len());
+        buf[..n].copy_from_slice(&plaintext[..n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for RangeReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+          SeekFrom::Start(offset) => Some(offset),
+          SeekFrom::End(offset) => self.plaintext_len.checked_add_signed(offset),
+          SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek to a negative or overflowing position"))?;
+        Ok(self.position)
+    }
+}
+
+impl<R> Drop for RangeReader<R> {
+    fn drop(&mut self) {
+        if let Some((_, plaintext)) = self.cached.as_mut() {
+          plaintext.zeroize();
+        }
+    }
+}
+
+/// Read until the buffer is full or the end of the input, returning the number of bytes read.
+pub(crate) fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+      match reader.read(&mut buf[filled..])? {
+        0 => break,
+        n => filled += n,
+      }
+    }
+    Ok(filled)
+}
+
+/// Turn an io::Error from a RangeReader or stream adapter back into the enchanter Error it wraps.
+pub(crate) fn unwrap_error(e: io::Error) -> Error {
+    if !e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+      return Error::from(e);
+    }
+    match e.into_inner().map(|inner| inner.downcast::<Error>()) {
+      Some(Ok(error)) => *error,
+      _ => Error::Failed("Failed to read the ciphertext".into()),
+    }
+}
+
+/// Decrypt len bytes of plaintext starting at offset, reading and authenticating only the chunks
+/// that cover the range. The range is cut off at the end of the plaintext, and an empty range
+/// still authenticates the nearest chunk.
+pub fn decrypt_range(path: &str, offset: u64, len: u64, key: &[u8]) -> Result<Vec<u8>, Error> {
+    let file = File::open(path).map_err(|e| Error::Io(format!("Failed to open the input file {path}: {e}")))?;
+    let mut reader = RangeReader::new(file, key)?;
+    let end = offset.saturating_add(len).min(reader.plaintext_len());
+    let mut plaintext = vec![0u8; end.saturating_sub(offset) as usize];
+    if plaintext.is_empty() && !reader.legacy {
+      // Still authenticate a chunk, so a wrong key or a truncated file never yields an empty success.
+      reader.load_chunk((offset / reader.chunk_size).min(reader.chunks - 1))?;
+    }
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut plaintext).map_err(unwrap_error)?;
+    Ok(plaintext)
+}
@@ -0,0 +1,118 @@
+//! Encrypted values inside serde-serialized structs, behind the `serde` feature.
+//!
+//! A `Sealed<T>` serializes as a single string, so it can be a field of any TOML or JSON config:
+//!
+//! ```toml
+//! database_password = "enchanter:JkD1...Q==:xshPOXhtqGJt...A=="
+//! ```
+//!
+//! The string is `enchanter:`, the base64 of the value serialized as JSON and encrypted into the
+//! single buffer format, then `:` and the validation string of that ciphertext. Unsealing checks
+//! the validation string before decrypting.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::str::FromStr;
+
+use base64::prelude::*;
+use rand_core::TryCryptoRng;
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use zeroize::Zeroize;
+
+use crate::crypt_xchacha::{self, Key};
+use crate::error::Error;
+
+const PREFIX: &str = "enchanter:";
+
+/// A value of type T, encrypted with a `Key`.
+pub struct Sealed<T> {
+    ciphertext: Vec<u8>,
+    validation_string: String,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Sealed<T> {
+    /// Serialize and encrypt a value.
+    pub fn seal(value: &T, key: &Key) -> Result<Sealed<T>, Error> {
+        Sealed::seal_with(value, |plaintext| crypt_xchacha::encrypt_bytes(plaintext, key))
+    }
+
+    /// The same as seal, with a nonce from the caller's RNG.
+    pub fn seal_with_rng<R: TryCryptoRng + ?Sized>(value: &T, key: &Key, rng: &mut R) -> Result<Sealed<T>, Error> {
+        Sealed::seal_with(value, |plaintext| crypt_xchacha::encrypt_bytes_with_rng(plaintext, key, rng))
+    }
+
+    fn seal_with(value: &T, encrypt: impl FnOnce(&[u8]) -> Result<crypt_xchacha::Encrypted, Error>) -> Result<Sealed<T>, Error> {
+        let mut plaintext = serde_json::to_vec(value).map_err(|e| Error::Failed(format!("Failed to serialize the sealed value: {e}")))?;
+        let encrypted = encrypt(&plaintext);
+        plaintext.zeroize();
+        let encrypted = encrypted?;
+        Ok(Sealed { ciphertext: encrypted.ciphertext, validation_string: encrypted.validation_string, value: PhantomData })
+    }
+
+    /// Check the validation string, then decrypt and deserialize the value. A wrong key is E_AUTH.
+    pub fn unseal(&self, key: &Key) -> Result<T, Error> {
+        let decrypted = crypt_xchacha::decrypt_validated(&self.ciphertext, key.as_bytes(), Some(&self.validation_string), 1)?;
+        serde_json::from_slice(&decrypted.plaintext).map_err(|e| Error::Malformed(format!("The sealed value does not deserialize: {e}")))
+    }
+}
+
+impl<T> Sealed<T> {
+    /// The validation string of the ciphertext.
+    pub fn validation_string(&self) -> &str {
+        &self.validation_string
+    }
+}
+
+impl<T> Clone for Sealed<T> {
+    fn clone(&self) -> Sealed<T> {
+        Sealed { ciphertext: self.ciphertext.clone(), validation_string: self.validation_string.clone(), value: PhantomData }
+    }
+}
+
+impl<T> PartialEq for Sealed<T> {
+    fn eq(&self, other: &Sealed<T>) -> bool {
+        self.ciphertext == other.ciphertext && self.validation_string == other.validation_string
+    }
+}
+
+impl<T> Eq for Sealed<T> {}
+
+impl<T> fmt::Debug for Sealed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Sealed").field(&self.validation_string).finish()
+    }
+}
+
+impl<T> fmt::Display for Sealed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{PREFIX}{}:{}", BASE64_STANDARD.encode(&self.ciphertext), self.validation_string)
+    }
+}
+
+/// Parse the armored string. This only checks its shape, the key is needed to check the contents.
+impl<T> FromStr for Sealed<T> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Sealed<T>, Error> {
+        let malformed = || Error::Malformed("A sealed value is enchanter:, the base64 ciphertext, : and the validation string".into());
+        let (ciphertext, validation_string) = s.strip_prefix(PREFIX).and_then(|s| s.split_once(':')).ok_or_else(malformed)?;
+        let ciphertext = BASE64_STANDARD.decode(ciphertext).map_err(|_| malformed())?;
+        crypt_xchacha::parse_any(&ciphertext)?;
+        Ok(Sealed { ciphertext, validation_string: validation_string.to_string(), value: PhantomData })
+    }
+}
+
+impl<T> Serialize for Sealed<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Sealed<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Sealed<T>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
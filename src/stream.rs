@@ -0,0 +1,376 @@
+//! `std::io` adapters that write and read the enchanter format on any stream.
+//!
+//! `EncryptWriter` encrypts everything written to it into an inner writer, and `DecryptReader`
+//! decrypts an inner reader. In the chunked format both work one chunk at a time, so a stream
+//! of any size is handled in constant memory. The single buffer format has its tag in front of
+//! the ciphertext and one tag over everything, so it is buffered in full.
+//!
+//! Both compute the validation string of the ciphertext as it passes through, the same one
+//! that is recorded in enchanter.toml for a file. The encryption and decryption themselves are
+//! done by `Encryptor` and `Decryptor`, which do no I/O and are shared with the async adapters.
+
+use base64::prelude::*;
+use rand::TryRngCore;
+use rand::rngs::OsRng;
+use sha3::{Shake256, digest::{Update, ExtendableOutput, XofReader}};
+use zeroize::Zeroize;
+
+use std::io::{self, Read, Write};
+
+use crate::crypt_xchacha::{self, ChunkCipher, CipherSuite, Format, CHUNKED_HEADER_LEN, CHUNKED_MAGIC, CHUNKED_VERSION, TAG_LEN};
+use crate::error::Error;
+
+/// How much ciphertext the readers ask of their inner reader at a time.
+pub(crate) const READ_SIZE: usize = 64 * 1024;
+
+/// The validation string hasher: SHAKE256 over the key and then the ciphertext.
+pub(crate) fn validator(key: &[u8]) -> Shake256 {
+    let mut hasher = Shake256::default();
+    hasher.update(key);
+    hasher
+}
+
+pub(crate) fn finish_validator(hasher: Shake256) -> String {
+    let mut reader = hasher.finalize_xof();
+    let mut hash = [0u8; 64];
+    XofReader::read(&mut reader, &mut hash);
+    BASE64_STANDARD.encode(hash)
+}
+
+/// Encrypts plaintext as it is pushed, appending the ciphertext to an output buffer.
+pub(crate) struct Encryptor {
+    key: [u8; 32],
+    cipher: ChunkCipher,
+    format: Format,
+    header: [u8; CHUNKED_HEADER_LEN],
+    buffer: Vec<u8>,
+    index: usize,
+    hasher: Option<Shake256>,
+}
+
+impl Encryptor {
+    /// Start encrypting in the given format and cipher suite, appending the chunked header to out.
+    pub(crate) fn new(key: &[u8], format: Format, suite: CipherSuite, out: &mut Vec<u8>) -> Result<Encryptor, Error> {
+        crypt_xchacha::check_key(key)?;
+        if format == Format::Legacy {
+          crypt_xchacha::check_legacy_suite(suite)?;
+        }
+        let mut encryptor = Encryptor {
+          key: key.try_into().unwrap_or_default(),
+          cipher: ChunkCipher::new(suite, key),
+          format,
+          header: [0u8; CHUNKED_HEADER_LEN],
+          buffer: Vec::new(),
+          index: 0,
+          hasher: Some(validator(key)),
+        };
+        if let Format::Chunked { chunk_size, .. } = format {
+          if chunk_size == 0 {
+            return Err(Error::Usage("The chunk size must be at least 1 byte".into()));
+          }
+          encryptor.header[..7].copy_from_slice(CHUNKED_MAGIC);
+          encryptor.header[7] = CHUNKED_VERSION;
+          encryptor.header[8] = suite.id();
+          encryptor.header[9..13].copy_from_slice(&chunk_size.to_be_bytes());
+          OsRng.try_fill_bytes(&mut encryptor.header[13..]).map_err(|e| Error::Failed(format!("Failed to generate a nonce: {e}")))?;
+          let header = encryptor.header;
+          encryptor.emit(&header, out)?;
+          encryptor.buffer.reserve(chunk_size as usize);
+        }
+        Ok(encryptor)
+    }
+
+    /// Append ciphertext to out and the validator.
+    fn emit(&mut self, bytes: &[u8], out: &mut Vec<u8>) -> Result<(), Error> {
+        let hasher = self.hasher.as_mut().ok_or_else(|| Error::Failed("The stream is already finished".into()))?;
+        hasher.update(bytes);
+        out.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Encrypt the buffered chunk into out.
+    fn seal_chunk(&mut self, last: bool, out: &mut Vec<u8>) -> Result<(), Error> {
+        if self.index > u32::MAX as usize {
+          return Err(Error::Failed("The stream has more chunks than the format allows, use a larger chunk size".into()));
+        }
+        let prefix = self.header[13..].first_chunk::<19>().copied().unwrap_or_default();
+        let mut chunk = std::mem::take(&mut self.buffer);
+        let tag = self.cipher.seal(&prefix, self.index, last, &self.header, &mut chunk)?;
+        self.emit(&chunk, out)?;
+        self.emit(&tag, out)?;
+        chunk.clear();
+        self.buffer = chunk;
+        self.index += 1;
+        Ok(())
+    }
+
+    /// Take plaintext, returning how much of it was taken. At most one chunk is encrypted into
+    /// out per call. A full chunk is only encrypted once more plaintext follows, so that the last
+    /// chunk is known.
+    pub(crate) fn push(&mut self, plaintext: &[u8], out: &mut Vec<u8>) -> Result<usize, Error> {
+        let Format::Chunked { chunk_size, .. } = self.format else {
+          self.buffer.extend_from_slice(plaintext);
+          return Ok(plaintext.len());
+        };
+        let size = chunk_size as usize;
+        if self.buffer.len() == size && !plaintext.is_empty() {
+          self.seal_chunk(false, out)?;
+        }
+        let n = (size - self.buffer.len()).min(plaintext.len());
+        self.buffer.extend_from_slice(&plaintext[..n]);
+        Ok(n)
+    }
+
+    /// Encrypt the rest of the plaintext into out and return the validation string of all the
+    /// ciphertext.
+    pub(crate) fn finish(&mut self, out: &mut Vec<u8>) -> Result<String, Error> {
+        match self.format {
+          Format::Chunked { .. } => self.seal_chunk(true, out)?,
+          Format::Legacy => {
+            let sealed = crypt_xchacha::seal(&self.buffer, &self.key)?;
+            self.buffer.zeroize();
+            self.emit(&sealed, out)?;
+          },
+        }
+        let hasher = self.hasher.take().ok_or_else(|| Error::Failed("The stream is already finished".into()))?;
+        Ok(finish_validator(hasher))
+    }
+}
+
+impl Drop for Encryptor {
+    fn drop(&mut self) {
+        self.buffer.zeroize();
+        self.key.zeroize();
+    }
+}
+
+/// Decrypts ciphertext as it is pushed, returning plaintext one authenticated chunk at a time.
+pub(crate) struct Decryptor {
+    key: [u8; 32],
+    detected: bool,
+    /// The header and cipher of a chunked stream.
+    header: Option<([u8; CHUNKED_HEADER_LEN], ChunkCipher)>,
+    input: Vec<u8>,
+    index: usize,
+    hasher: Option<Shake256>,
+    validation_string: Option<String>,
+}
+
+impl Decryptor {
+    pub(crate) fn new(key: &[u8]) -> Result<Decryptor, Error> {
+        crypt_xchacha::check_key(key)?;
+        Ok(Decryptor {
+          key: key.try_into().unwrap_or_default(),
+          detected: false,
+          header: None,
+          input: Vec::new(),
+          index: 0,
+          hasher: Some(validator(key)),
+          validation_string: None,
+        })
+    }
+
+    /// Take ciphertext read from the stream.
+    pub(crate) fn push(&mut self, ciphertext: &[u8]) {
+        if let Some(hasher) = self.hasher.as_mut() {
+          hasher.update(ciphertext);
+          self.input.extend_from_slice(ciphertext);
+        }
+    }
+
+    /// Whether the whole ciphertext has been decrypted.
+    pub(crate) fn done(&self) -> bool {
+        self.hasher.is_none()
+    }
+
+    /// The validation string of the whole ciphertext, once it has been decrypted.
+    pub(crate) fn validation_string(&self) -> Option<&str> {
+        self.validation_string.as_deref()
+    }
+
+    fn finish(&mut self) {
+        if let Some(hasher) = self.hasher.take() {
+          self.validation_string = Some(finish_validator(hasher));
+        }
+    }
+
+    /// Decrypt the next chunk, or None when more ciphertext has to be pushed first. eof is
+    /// whether the end of the stream has been reached, which is needed to know the last chunk.
+    pub(crate) fn next(&mut self, eof: bool) -> Result<Option<Vec<u8>>, Error> {
+        if self.done() {
+          return Ok(None);
+        }
+        if !self.detected {
+          if self.input.len() < CHUNKED_HEADER_LEN && !eof {
+            return Ok(None);
+          }
+          self.detected = true;
+          if crypt_xchacha::is_chunked(&self.input) {
+            let parsed = crypt_xchacha::parse_chunked_header(&self.input)?;
+            self.header = Some((*parsed.bytes, ChunkCipher::new(parsed.suite, &self.key)));
+            self.input.drain(..CHUNKED_HEADER_LEN);
+          }
+        }
+        let Some((header, cipher)) = &self.header else {
+          if !eof {
+            return Ok(None);
+          }
+          let plaintext = crypt_xchacha::open(&self.input, &self.key)?;
+          self.finish();
+          return Ok(Some(plaintext));
+        };
+
+        let stride = u32::from_be_bytes([header[9], header[10], header[11], header[12]]) as usize + TAG_LEN;
+        let last = self.input.len() <= stride;
+        if last && !eof {
+          return Ok(None);
+        }
+        let len = self.input.len().min(stride);
+        if len < TAG_LEN {
+          let error = if len == 0 && self.index > 0 {
+            Error::Truncated("The ciphertext ends before its last chunk".into())
+          } else {
+            Error::Malformed("The last chunk is too short for a tag".into())
+          };
+          return Err(error);
+        }
+        if self.index > u32::MAX as usize {
+          return Err(Error::Malformed("The ciphertext has more chunks than the format allows".into()));
+        }
+        let mut chunk: Vec<u8> = self.input.drain(..len).collect();
+        let (ciphertext, tag) = chunk.split_at_mut(len - TAG_LEN);
+        let prefix = header[13..].first_chunk::<19>().copied().unwrap_or_default();
+        if let Err(e) = cipher.open(&prefix, self.index, last, header, ciphertext, tag) {
+          chunk.zeroize();
+          return Err(e);
+        }
+        chunk.truncate(len - TAG_LEN);
+        self.index += 1;
+        if last {
+          self.finish();
+        }
+        Ok(Some(chunk))
+    }
+}
+
+impl Drop for Decryptor {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+/// Encrypts everything written to it into the inner writer.
+///
+/// `finish` must be called to write the last chunk and its tag. A stream that is dropped
+/// without it is incomplete and fails to decrypt.
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    encryptor: Encryptor,
+    out: Vec<u8>,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    /// Start encrypting into the writer in the given format. The chunked header is written
+    /// right away, and the number of threads of a chunked format is not used.
+    pub fn new(inner: W, key: &[u8], format: Format) -> Result<EncryptWriter<W>, Error> {
+        EncryptWriter::with_suite(inner, key, format, CipherSuite::XChaCha20Poly1305)
+    }
+
+    /// The same as new, in the given cipher suite, which needs the chunked format unless it is
+    /// XChaCha20Poly1305.
+    pub fn with_suite(mut inner: W, key: &[u8], format: Format, suite: CipherSuite) -> Result<EncryptWriter<W>, Error> {
+        let mut out = Vec::new();
+        let encryptor = Encryptor::new(key, format, suite, &mut out)?;
+        inner.write_all(&out).map_err(|e| Error::Io(format!("Failed to write the ciphertext: {e}")))?;
+        out.clear();
+        Ok(EncryptWriter { inner, encryptor, out })
+    }
+
+    fn write_out(&mut self) -> io::Result<()> {
+        if !self.out.is_empty() {
+          self.inner.write_all(&self.out)?;
+          self.out.clear();
+        }
+        Ok(())
+    }
+
+    /// Encrypt the rest of the plaintext, flush the inner writer, and return it with the
+    /// validation string of everything that was written.
+    pub fn finish(mut self) -> Result<(W, String), Error> {
+        let validation_string = self.encryptor.finish(&mut self.out)?;
+        self.write_out().and_then(|_| self.inner.flush()).map_err(|e| Error::Io(format!("Failed to write the ciphertext: {e}")))?;
+        Ok((self.inner, validation_string))
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut taken = 0;
+        while taken < buf.len() {
+          taken += self.encryptor.push(&buf[taken..], &mut self.out).map_err(io::Error::other)?;
+          self.write_out()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts the enchanter format from the inner reader.
+///
+/// Chunked streams are read one chunk at a time, and only authenticated plaintext is returned.
+/// Single buffer streams are read and authenticated in full on the first read.
+/// Errors are `io::Error`s wrapping an enchanter `Error`.
+pub struct DecryptReader<R: Read> {
+    inner: R,
+    decryptor: Decryptor,
+    eof: bool,
+    /// Decrypted plaintext that has not been read yet, from position onwards.
+    plaintext: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> DecryptReader<R> {
+    /// Start decrypting the reader. The format is detected from the first bytes.
+    pub fn new(inner: R, key: &[u8]) -> Result<DecryptReader<R>, Error> {
+        Ok(DecryptReader { inner, decryptor: Decryptor::new(key)?, eof: false, plaintext: Vec::new(), position: 0 })
+    }
+
+    /// The validation string of the whole ciphertext, once everything has been read.
+    pub fn validation_string(&self) -> Option<&str> {
+        self.decryptor.validation_string()
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut scratch = Vec::new();
+        while self.position == self.plaintext.len() && !self.decryptor.done() {
+          if let Some(plaintext) = self.decryptor.next(self.eof).map_err(io::Error::other)? {
+            self.plaintext.zeroize();
+            self.plaintext = plaintext;
+            self.position = 0;
+            continue;
+          }
+          scratch.resize(READ_SIZE, 0);
+          match self.inner.read(&mut scratch) {
+            Ok(0) => self.eof = true,
+            Ok(n) => self.decryptor.push(&scratch[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(io::Error::other(Error::Io(format!("Failed to read the ciphertext: {e}")))),
+          }
+        }
+        let n = (self.plaintext.len() - self.position).min(buf.len());
+        buf[..n].copy_from_slice(&self.plaintext[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Drop for DecryptReader<R> {
+    fn drop(&mut self) {
+        self.plaintext.zeroize();
+    }
+}
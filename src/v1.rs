@@ -0,0 +1,57 @@
+//! The stable library API.
+//!
+//! Everything in this module keeps its name, signature and behavior across releases, following
+//! semver: anything that would change is added alongside under a new name, or in a later `v2`
+//! module, while `v1` stays as it is. These are the same items as at the crate root, so the
+//! items re-exported here are held to the same promise there. `Format`, `CipherSuite`, `Error`,
+//! `Decrypted` and `Encrypted` are non-exhaustive, so variants and fields can be added without
+//! breaking callers.
+//!
+//! Keys come from `derive_key`, which matches the enchanter command, so files can be shared
+//! between the library and the command. Everything that writes ciphertext returns its
+//! validation string, the `ciphertext_hash` of an enchanter.toml.
+//!
+//! The file, stream and range functions and `encrypt_bytes` need the `std` feature, `Manifest`
+//! the `manifest` feature, and `Sealed` the `serde` feature. Without `std`, `encrypt_bytes_with_rng`
+//! takes the nonce RNG from the caller.
+
+pub use crate::crypt_xchacha::{decrypt_bytes, encrypt_bytes_with_rng, validate, validation_string, CipherSuite, Decrypted, Encrypted, Format, Key, DEFAULT_CHUNK_SIZE};
+#[cfg(feature = "std")]
+pub use crate::crypt_xchacha::encrypt_bytes;
+pub use crate::error::Error;
+#[cfg(feature = "manifest")]
+pub use crate::manifest::Manifest;
+#[cfg(feature = "std")]
+pub use crate::range::{decrypt_range, RangeReader};
+#[cfg(feature = "serde")]
+pub use crate::sealed::Sealed;
+#[cfg(feature = "std")]
+pub use crate::stream::{DecryptReader, EncryptWriter};
+
+use crate::crypt_xchacha::TUR;
+
+/// Derive a key from a password the way the enchanter command does, with three rounds of Argon2id.
+pub fn derive_key(password: &[u8]) -> Result<Key, Error> {
+    Key::from_password(password, TUR)
+}
+
+/// Encrypt a file in the given format and return the validation string of the output file.
+#[cfg(feature = "std")]
+pub fn encrypt_file(input_file: &str, output_file: &str, key: &Key, format: Format) -> Result<String, Error> {
+    crate::crypt_xchacha::encrypt_file_format(input_file, output_file, key.as_bytes(), format)
+}
+
+/// Encrypt a file in the given format and cipher suite and return the validation string of the
+/// output file. Suites other than XChaCha20Poly1305 need the chunked format.
+#[cfg(feature = "std")]
+pub fn encrypt_file_suite(input_file: &str, output_file: &str, key: &Key, format: Format, suite: CipherSuite) -> Result<String, Error> {
+    crate::crypt_xchacha::encrypt_file_suite(input_file, output_file, key.as_bytes(), format, suite)
+}
+
+/// Decrypt a file in either format and cipher suite and return its validation string. With an
+/// expected validation string, a mismatch is an E_AUTH error and the output file is not created.
+/// Chunked files are decrypted on all cores.
+#[cfg(feature = "std")]
+pub fn decrypt_file(input_file: &str, output_file: &str, key: &Key, expected: Option<&str>) -> Result<String, Error> {
+    crate::crypt_xchacha::decrypt_file_validated(input_file, output_file, key.as_bytes(), expected, 0)
+}
@@ -0,0 +1,2 @@
+a+-6
+٤f镸7ONac"Lx[2L~GyQoa8IYW\St|_|(Szl&=Bxkuɲ(w|
\ No newline at end of file
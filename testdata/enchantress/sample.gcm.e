@@ -0,0 +1,2 @@
+f#%enchantress sample plaintext
+line two of the sample, for the compatibility tests.